@@ -0,0 +1,830 @@
+//! Generates Rust code for the structs of a Move module from its ABI, so the on-chain
+//! representations don't have to be copied from the contract by hand:
+//! - `*OnChain` serde structs matching the JSON the transaction stream renders values as
+//! - Diesel models, with a conversion from the `*OnChain` structs
+//! - a migration creating the models' tables, and their `diesel::table!` definitions
+//!
+//! The output is a starting point to review and trim. Every table is keyed by
+//! (txn_version, event_index), which fits events but not resources, and fields that don't map
+//! to a single column, e.g. nested structs and vectors, are stored as JSONB.
+
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::{move_type::Content, MoveType, MoveTypes},
+    utils::convert::standardize_address,
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::indexer_processor_config::DbConfig,
+    schema::module_upgrade_history,
+    steps::extractor::parse_event_type,
+    utils::{database_connection::establish_connection, move_abi::parse_module_abi},
+};
+
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "const", "continue", "crate", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Type of a struct field, from either ABI format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    Address,
+    Signer,
+    Vector(Box<FieldType>),
+    Struct {
+        address: String,
+        module: String,
+        name: String,
+        type_args: Vec<FieldType>,
+    },
+    // A type parameter of the struct, only known per instance
+    Generic,
+}
+
+impl FieldType {
+    /// Parses a type as written in the REST API's ABI, e.g. `vector<0x1::string::String>`.
+    pub fn parse(type_str: &str) -> Result<Self> {
+        let type_str = type_str.trim();
+        let type_str = type_str
+            .strip_prefix("&mut ")
+            .or_else(|| type_str.strip_prefix('&'))
+            .unwrap_or(type_str);
+        Ok(match type_str {
+            "bool" => Self::Bool,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "u128" => Self::U128,
+            "u256" => Self::U256,
+            "address" => Self::Address,
+            "signer" => Self::Signer,
+            _ => {
+                if let Some(item_type) = type_str
+                    .strip_prefix("vector<")
+                    .and_then(|item_type| item_type.strip_suffix('>'))
+                {
+                    Self::Vector(Box::new(Self::parse(item_type)?))
+                } else if type_str.len() > 1
+                    && type_str.starts_with('T')
+                    && type_str[1..].chars().all(|c| c.is_ascii_digit())
+                {
+                    Self::Generic
+                } else {
+                    let (address, module, name, type_args) = parse_event_type(type_str)
+                        .with_context(|| format!("Unknown type {}", type_str))?;
+                    Self::Struct {
+                        address,
+                        module,
+                        name,
+                        type_args: type_args
+                            .iter()
+                            .map(|type_arg| Self::parse(type_arg))
+                            .collect::<Result<_>>()?,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Converts a type of the transaction protos, as stored in module_upgrade_history.
+    pub fn from_proto(move_type: &MoveType) -> Result<Self> {
+        Ok(match (move_type.r#type(), &move_type.content) {
+            (MoveTypes::Bool, _) => Self::Bool,
+            (MoveTypes::U8, _) => Self::U8,
+            (MoveTypes::U16, _) => Self::U16,
+            (MoveTypes::U32, _) => Self::U32,
+            (MoveTypes::U64, _) => Self::U64,
+            (MoveTypes::U128, _) => Self::U128,
+            (MoveTypes::U256, _) => Self::U256,
+            (MoveTypes::Address, _) => Self::Address,
+            (MoveTypes::Signer, _) => Self::Signer,
+            (MoveTypes::Vector, Some(Content::Vector(item_type))) => {
+                Self::Vector(Box::new(Self::from_proto(item_type)?))
+            }
+            (MoveTypes::Struct, Some(Content::Struct(struct_tag))) => Self::Struct {
+                address: standardize_address(&struct_tag.address),
+                module: struct_tag.module.clone(),
+                name: struct_tag.name.clone(),
+                type_args: struct_tag
+                    .generic_type_params
+                    .iter()
+                    .map(Self::from_proto)
+                    .collect::<Result<_>>()?,
+            },
+            (MoveTypes::GenericTypeParam, _) => Self::Generic,
+            (MoveTypes::Reference, Some(Content::Reference(reference))) => Self::from_proto(
+                reference
+                    .to
+                    .as_deref()
+                    .context("Reference type without a referenced type")?,
+            )?,
+            _ => anyhow::bail!("Unsupported type {:?}", move_type),
+        })
+    }
+
+    fn is_framework_struct(&self, module_name: &str, struct_name: &str) -> bool {
+        matches!(self, Self::Struct { address, module, name, .. }
+            if *address == standardize_address("0x1") && module == module_name && name == struct_name)
+    }
+
+    /// Item type of an `0x1::option::Option`.
+    fn option_item(&self) -> Option<&FieldType> {
+        match self {
+            Self::Struct { type_args, .. } if self.is_framework_struct("option", "Option") => {
+                type_args.first()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Struct of a Move module ABI.
+#[derive(Clone, Debug)]
+pub struct AbiStruct {
+    pub name: String,
+    pub is_native: bool,
+    pub fields: Vec<(String, FieldType)>,
+}
+
+/// The parts of a Move module ABI code is generated from.
+#[derive(Clone, Debug)]
+pub struct AbiModule {
+    pub address: String,
+    pub name: String,
+    pub structs: Vec<AbiStruct>,
+}
+
+#[derive(Deserialize)]
+struct RestModule {
+    address: String,
+    name: String,
+    structs: Vec<RestStruct>,
+}
+
+#[derive(Deserialize)]
+struct RestStruct {
+    name: String,
+    #[serde(default)]
+    is_native: bool,
+    fields: Vec<RestField>,
+}
+
+#[derive(Deserialize)]
+struct RestField {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: String,
+}
+
+impl AbiModule {
+    /// Reads the response of the REST API's module endpoint
+    /// (`/accounts/{address}/module/{name}`) or its `abi`, or a `module_abi` of
+    /// module_upgrade_history.
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let value = value.get("abi").unwrap_or(value);
+        if let Ok(module) = serde_json::from_value::<RestModule>(value.clone()) {
+            return Ok(Self {
+                address: standardize_address(&module.address),
+                name: module.name,
+                structs: module
+                    .structs
+                    .into_iter()
+                    .map(|move_struct| {
+                        Ok(AbiStruct {
+                            fields: move_struct
+                                .fields
+                                .iter()
+                                .map(|field| {
+                                    Ok((field.name.clone(), FieldType::parse(&field.field_type)?))
+                                })
+                                .collect::<Result<_>>()
+                                .with_context(|| format!("Invalid struct {}", move_struct.name))?,
+                            name: move_struct.name,
+                            is_native: move_struct.is_native,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            });
+        }
+        let module = parse_module_abi(value)?;
+        Ok(Self {
+            address: standardize_address(&module.address),
+            name: module.name.clone(),
+            structs: module
+                .structs
+                .iter()
+                .map(|move_struct| {
+                    Ok(AbiStruct {
+                        name: move_struct.name.clone(),
+                        is_native: move_struct.is_native,
+                        fields: move_struct
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                let move_type = field
+                                    .r#type
+                                    .as_ref()
+                                    .with_context(|| format!("Field {} has no type", field.name))?;
+                                Ok((field.name.clone(), FieldType::from_proto(move_type)?))
+                            })
+                            .collect::<Result<_>>()
+                            .with_context(|| format!("Invalid struct {}", move_struct.name))?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn is_own_struct(&self, field_type: &FieldType) -> Option<&AbiStruct> {
+        match field_type {
+            FieldType::Struct {
+                address,
+                module,
+                name,
+                ..
+            } if *address == self.address && *module == self.name => self
+                .structs
+                .iter()
+                .find(|move_struct| move_struct.name == *name && !move_struct.is_native),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a module's ABI from module_upgrade_history, at the latest upgrade when
+/// `upgrade_number` is None.
+pub async fn load_module_abi(
+    db_config: &DbConfig,
+    module_addr: &str,
+    module_name: &str,
+    upgrade_number: Option<i64>,
+) -> Result<Value> {
+    let mut conn = establish_connection(&db_config.postgres_connection_string)
+        .await
+        .context("Failed to connect to the database")?;
+    let mut query = module_upgrade_history::table
+        .filter(module_upgrade_history::module_addr.eq(standardize_address(module_addr)))
+        .filter(module_upgrade_history::module_name.eq(module_name))
+        .select(module_upgrade_history::module_abi)
+        .order(module_upgrade_history::upgrade_number.desc())
+        .into_boxed();
+    if let Some(upgrade_number) = upgrade_number {
+        query = query.filter(module_upgrade_history::upgrade_number.eq(upgrade_number));
+    }
+    query.first::<Value>(&mut conn).await.with_context(|| {
+        format!(
+            "No upgrade of module {}::{} found",
+            module_addr, module_name
+        )
+    })
+}
+
+/// How a field is stored in its model's table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Storage {
+    Bool,
+    // u8 and u16
+    Int,
+    // u32
+    BigInt,
+    // u64, rendered as a decimal string
+    ParsedBigInt,
+    // u128 and u256, rendered as decimal strings
+    ParsedNumeric,
+    Address,
+    ObjectAddress,
+    // Strings, and byte vectors as hex strings
+    Text,
+    Json,
+}
+
+impl Storage {
+    fn of(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Bool => Self::Bool,
+            FieldType::U8 | FieldType::U16 => Self::Int,
+            FieldType::U32 => Self::BigInt,
+            FieldType::U64 => Self::ParsedBigInt,
+            FieldType::U128 | FieldType::U256 => Self::ParsedNumeric,
+            FieldType::Address | FieldType::Signer => Self::Address,
+            FieldType::Vector(item_type) if **item_type == FieldType::U8 => Self::Text,
+            _ if field_type.is_framework_struct("string", "String") => Self::Text,
+            _ if field_type.is_framework_struct("object", "Object") => Self::ObjectAddress,
+            _ => Self::Json,
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::Int => "i32",
+            Self::BigInt | Self::ParsedBigInt => "i64",
+            Self::ParsedNumeric => "bigdecimal::BigDecimal",
+            Self::Address | Self::ObjectAddress | Self::Text => "String",
+            Self::Json => "serde_json::Value",
+        }
+    }
+
+    fn sql_type(self) -> &'static str {
+        match self {
+            Self::Bool => "BOOLEAN",
+            Self::Int => "INT",
+            Self::BigInt | Self::ParsedBigInt => "BIGINT",
+            Self::ParsedNumeric => "NUMERIC",
+            Self::Address | Self::ObjectAddress => "VARCHAR(300)",
+            Self::Text => "TEXT",
+            Self::Json => "JSONB",
+        }
+    }
+
+    fn diesel_type(self) -> &'static str {
+        match self {
+            Self::Bool => "Bool",
+            Self::Int => "Int4",
+            Self::BigInt | Self::ParsedBigInt => "Int8",
+            Self::ParsedNumeric => "Numeric",
+            Self::Address | Self::ObjectAddress => "Varchar",
+            Self::Text => "Text",
+            Self::Json => "Jsonb",
+        }
+    }
+
+    fn is_fallible(self) -> bool {
+        matches!(self, Self::ParsedBigInt | Self::ParsedNumeric | Self::Json)
+    }
+
+    /// Expression converting `place`, a value of the on-chain type, to the model's type.
+    /// `owned` is whether `place` can be moved out of.
+    fn conversion(self, field_name: &str, place: &str, owned: bool) -> String {
+        match self {
+            Self::Bool => place.to_string(),
+            Self::Int => format!("i32::from({})", place),
+            Self::BigInt => format!("i64::from({})", place),
+            Self::ParsedBigInt | Self::ParsedNumeric => {
+                format!("{}.parse().context(\"Invalid {}\")?", place, field_name)
+            }
+            Self::Address => format!("standardize_address(&{})", place),
+            Self::ObjectAddress => format!("standardize_address(&{}.inner)", place),
+            Self::Text if owned => place.to_string(),
+            Self::Text => format!("{}.clone()", place),
+            Self::Json => format!("serde_json::to_value(&{})?", place),
+        }
+    }
+}
+
+/// A column of a generated model.
+struct Column {
+    name: String,
+    storage: Storage,
+    nullable: bool,
+    conversion: String,
+}
+
+impl Column {
+    fn new(field_name: &str, field_type: &FieldType) -> Self {
+        let name = rust_ident(field_name);
+        let place = format!("self.{}", name);
+        match field_type.option_item() {
+            Some(item_type) => {
+                let storage = Storage::of(item_type);
+                let item_conversion = storage.conversion(field_name, "value", true);
+                let items = format!("{}.vec.first().cloned()", place);
+                let conversion = if item_conversion == "value" {
+                    items
+                } else if storage.is_fallible() {
+                    format!(
+                        "{}\n                .map(|value| -> Result<_> {{ Ok({}) }})\n                .transpose()?",
+                        items, item_conversion
+                    )
+                } else {
+                    format!("{}.map(|value| {})", items, item_conversion)
+                };
+                Self {
+                    name,
+                    storage,
+                    nullable: true,
+                    conversion,
+                }
+            }
+            None => {
+                let storage = Storage::of(field_type);
+                Self {
+                    conversion: storage.conversion(field_name, &place, false),
+                    name,
+                    storage,
+                    nullable: false,
+                }
+            }
+        }
+    }
+}
+
+/// Code generated for a module, see the module docs.
+#[derive(Clone, Debug, Default)]
+pub struct GeneratedCode {
+    pub models: String,
+    pub schema: String,
+    pub up_sql: String,
+    pub down_sql: String,
+}
+
+fn rust_ident(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (idx, c) in name.char_indices() {
+        if c.is_ascii_uppercase() {
+            if idx > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn table_name(struct_name: &str) -> String {
+    let snake = to_snake_case(struct_name);
+    if snake.ends_with('s') {
+        snake
+    } else {
+        format!("{}s", snake)
+    }
+}
+
+/// Rust type a value of `field_type` deserializes into from the transaction stream's JSON.
+fn on_chain_type(module: &AbiModule, field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Bool => "bool".to_string(),
+        FieldType::U8 => "u8".to_string(),
+        FieldType::U16 => "u16".to_string(),
+        FieldType::U32 => "u32".to_string(),
+        // Integers that don't fit in a JSON number are rendered as decimal strings
+        FieldType::U64 | FieldType::U128 | FieldType::U256 => "String".to_string(),
+        FieldType::Address | FieldType::Signer => "String".to_string(),
+        // Byte vectors are rendered as hex strings
+        FieldType::Vector(item_type) if **item_type == FieldType::U8 => "String".to_string(),
+        FieldType::Vector(item_type) => format!("Vec<{}>", on_chain_type(module, item_type)),
+        _ if field_type.is_framework_struct("string", "String") => "String".to_string(),
+        _ if field_type.is_framework_struct("option", "Option") => format!(
+            "OptionOnChain<{}>",
+            field_type
+                .option_item()
+                .map_or("serde_json::Value".to_string(), |item_type| on_chain_type(
+                    module, item_type
+                ))
+        ),
+        _ if field_type.is_framework_struct("object", "Object") => "ObjectOnChain".to_string(),
+        _ => match module.is_own_struct(field_type) {
+            Some(move_struct) => format!("{}OnChain", move_struct.name),
+            // Generic type parameters and structs of other modules, whose ABI isn't at hand
+            None => "serde_json::Value".to_string(),
+        },
+    }
+}
+
+/// Generates the code for the models of `struct_names`, all structs of the module when empty.
+/// `*OnChain` structs are generated for every struct, models may need them for their fields.
+pub fn generate(module: &AbiModule, struct_names: &[String]) -> Result<GeneratedCode> {
+    let structs = module
+        .structs
+        .iter()
+        .filter(|move_struct| !move_struct.is_native)
+        .collect::<Vec<_>>();
+    for struct_name in struct_names {
+        if !structs
+            .iter()
+            .any(|move_struct| move_struct.name == *struct_name)
+        {
+            anyhow::bail!("Struct {} is not in module {}", struct_name, module.name);
+        }
+    }
+    let model_structs = structs
+        .iter()
+        .filter(|move_struct| struct_names.is_empty() || struct_names.contains(&move_struct.name))
+        .collect::<Vec<_>>();
+
+    let mut on_chain = String::new();
+    for move_struct in &structs {
+        writeln!(on_chain)?;
+        writeln!(on_chain, "#[derive(Clone, Debug, Deserialize, Serialize)]")?;
+        writeln!(
+            on_chain,
+            "/// On-chain representation of {}::{}::{}",
+            module.address, module.name, move_struct.name
+        )?;
+        writeln!(on_chain, "pub struct {}OnChain {{", move_struct.name)?;
+        for (field_name, field_type) in &move_struct.fields {
+            writeln!(
+                on_chain,
+                "    pub {}: {},",
+                rust_ident(field_name),
+                on_chain_type(module, field_type)
+            )?;
+        }
+        writeln!(on_chain, "}}")?;
+    }
+
+    let mut models = String::new();
+    let mut schema = String::new();
+    let mut up_sql = String::from("-- Your SQL goes here");
+    let mut down_sql = String::from("-- This file should undo anything in `up.sql`");
+    let mut uses_numeric = false;
+    for (idx, move_struct) in model_structs.iter().enumerate() {
+        let table = table_name(&move_struct.name);
+        let snake_name = to_snake_case(&move_struct.name);
+        let columns = move_struct
+            .fields
+            .iter()
+            .map(|(field_name, field_type)| Column::new(field_name, field_type))
+            .collect::<Vec<_>>();
+        let has_numeric = columns
+            .iter()
+            .any(|column| column.storage == Storage::ParsedNumeric);
+        uses_numeric |= has_numeric;
+
+        // Model
+        writeln!(models)?;
+        if has_numeric {
+            // BigDecimal only implements serde with its serde feature
+            writeln!(
+                models,
+                "#[derive(AsChangeset, Clone, Debug, FieldCount, Insertable)]"
+            )?;
+        } else {
+            writeln!(
+                models,
+                "#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]"
+            )?;
+        }
+        writeln!(models, "#[diesel(table_name = {})]", table)?;
+        writeln!(
+            models,
+            "/// Database representation of {}::{}",
+            module.name, move_struct.name
+        )?;
+        writeln!(models, "pub struct {} {{", move_struct.name)?;
+        writeln!(models, "    pub txn_version: i64,")?;
+        writeln!(models, "    pub event_index: i64,")?;
+        for column in &columns {
+            let rust_type = column.storage.rust_type();
+            if column.nullable {
+                writeln!(models, "    pub {}: Option<{}>,", column.name, rust_type)?;
+            } else {
+                writeln!(models, "    pub {}: {},", column.name, rust_type)?;
+            }
+        }
+        writeln!(models, "}}")?;
+
+        // Conversion
+        writeln!(models)?;
+        writeln!(models, "impl {}OnChain {{", move_struct.name)?;
+        writeln!(
+            models,
+            "    pub fn to_db_{}(&self, txn_version: i64, event_index: i64) -> Result<{}> {{",
+            snake_name, move_struct.name
+        )?;
+        writeln!(models, "        Ok({} {{", move_struct.name)?;
+        writeln!(models, "            txn_version,")?;
+        writeln!(models, "            event_index,")?;
+        for column in &columns {
+            writeln!(
+                models,
+                "            {}: {},",
+                column.name, column.conversion
+            )?;
+        }
+        writeln!(models, "        }})")?;
+        writeln!(models, "    }}")?;
+        writeln!(models, "}}")?;
+
+        // Schema
+        if idx > 0 {
+            writeln!(schema)?;
+        }
+        writeln!(schema, "diesel::table! {{")?;
+        writeln!(schema, "    {} (txn_version, event_index) {{", table)?;
+        writeln!(schema, "        txn_version -> Int8,")?;
+        writeln!(schema, "        event_index -> Int8,")?;
+        for column in &columns {
+            if column.storage.sql_type().starts_with("VARCHAR") {
+                writeln!(schema, "        #[max_length = 300]")?;
+            }
+            if column.nullable {
+                writeln!(
+                    schema,
+                    "        {} -> Nullable<{}>,",
+                    column.name,
+                    column.storage.diesel_type()
+                )?;
+            } else {
+                writeln!(
+                    schema,
+                    "        {} -> {},",
+                    column.name,
+                    column.storage.diesel_type()
+                )?;
+            }
+        }
+        writeln!(schema, "    }}")?;
+        writeln!(schema, "}}")?;
+
+        // Migration
+        writeln!(up_sql)?;
+        writeln!(up_sql, "CREATE TABLE")?;
+        writeln!(up_sql, "    {} (", table)?;
+        writeln!(up_sql, "        txn_version BIGINT NOT NULL,")?;
+        writeln!(up_sql, "        event_index BIGINT NOT NULL,")?;
+        for column in &columns {
+            writeln!(
+                up_sql,
+                "        {} {}{},",
+                column.name.trim_start_matches("r#"),
+                column.storage.sql_type(),
+                if column.nullable { "" } else { " NOT NULL" }
+            )?;
+        }
+        writeln!(
+            up_sql,
+            "        -- Fits events, key resources by e.g. their object address instead"
+        )?;
+        write!(
+            up_sql,
+            "        PRIMARY KEY (txn_version, event_index)\n    );"
+        )?;
+        write!(down_sql, "\nDROP TABLE IF EXISTS {};", table)?;
+    }
+
+    let mut header = format!(
+        "// Generated from the ABI of {}::{}, review before use\n",
+        module.address, module.name
+    );
+    if uses_numeric {
+        header.push_str("// u128 and u256 fields are stored as bigdecimal::BigDecimal, which needs the bigdecimal\n// crate as a dependency\n");
+    }
+    header.push_str(if models.contains(".context(") {
+        "\nuse anyhow::{Context, Result};\n"
+    } else {
+        "\nuse anyhow::Result;\n"
+    });
+    if models.contains("standardize_address(") {
+        header.push_str("use aptos_indexer_processor_sdk::utils::convert::standardize_address;\n");
+    }
+    header.push_str(
+        "use diesel::{AsChangeset, Insertable};\nuse field_count::FieldCount;\nuse serde::{Deserialize, Serialize};\n\n",
+    );
+    writeln!(
+        header,
+        "use crate::schema::{{{}}};",
+        model_structs
+            .iter()
+            .map(|move_struct| table_name(&move_struct.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    let mut helpers = String::new();
+    if on_chain.contains("OptionOnChain<") {
+        helpers.push_str("\n#[derive(Clone, Debug, Deserialize, Serialize)]\n/// On-chain representation of 0x1::option::Option\npub struct OptionOnChain<T> {\n    pub vec: Vec<T>,\n}\n");
+    }
+    if on_chain.contains("ObjectOnChain") {
+        helpers.push_str("\n#[derive(Clone, Debug, Deserialize, Serialize)]\n/// On-chain representation of 0x1::object::Object\npub struct ObjectOnChain {\n    pub inner: String,\n}\n");
+    }
+
+    Ok(GeneratedCode {
+        models: format!("{}{}{}{}", header, helpers, on_chain, models),
+        schema,
+        up_sql,
+        down_sql,
+    })
+}
+
+/// Writes the generated code under `out_dir` and returns the paths written:
+/// `<module>.rs` for `db_models`, `schema.rs` to merge into the schema, and a migration
+/// directory to move into `db_migrations/migrations`.
+pub fn write_generated(
+    out_dir: &Path,
+    module: &AbiModule,
+    code: &GeneratedCode,
+) -> Result<Vec<PathBuf>> {
+    let migration_dir = out_dir.join(format!(
+        "{}_create-{}-tables",
+        chrono::Utc::now().format("%Y-%m-%d-%H%M%S"),
+        module.name.replace('_', "-")
+    ));
+    std::fs::create_dir_all(&migration_dir)
+        .with_context(|| format!("Failed to create {}", migration_dir.display()))?;
+    let files = [
+        (out_dir.join(format!("{}.rs", module.name)), &code.models),
+        (out_dir.join("schema.rs"), &code.schema),
+        (migration_dir.join("up.sql"), &code.up_sql),
+        (migration_dir.join("down.sql"), &code.down_sql),
+    ];
+    for (path, contents) in &files {
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message_board_abi() -> Value {
+        serde_json::json!({
+            "address": "0xcafe",
+            "name": "custom_indexer_ex_message_board",
+            "friends": [],
+            "exposed_functions": [],
+            "structs": [
+                {
+                    "name": "Message",
+                    "is_native": false,
+                    "abilities": ["copy", "drop", "store", "key"],
+                    "generic_type_params": [],
+                    "fields": [
+                        { "name": "creator", "type": "address" },
+                        { "name": "content", "type": "0x1::string::String" },
+                        { "name": "creation_timestamp", "type": "u64" },
+                        { "name": "last_update_timestamp", "type": "u64" }
+                    ]
+                },
+                {
+                    "name": "CreateMessageEvent",
+                    "is_native": false,
+                    "abilities": ["drop", "store"],
+                    "generic_type_params": [],
+                    "fields": [
+                        { "name": "message_obj_addr", "type": "address" },
+                        { "name": "message", "type": "0xcafe::custom_indexer_ex_message_board::Message" },
+                        { "name": "reply_to", "type": "0x1::option::Option<0x1::object::Object<0xcafe::custom_indexer_ex_message_board::Message>>" }
+                    ]
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_parse_field_type() {
+        assert_eq!(
+            FieldType::parse("vector<0x1::option::Option<u128>>").unwrap(),
+            FieldType::Vector(Box::new(FieldType::Struct {
+                address: standardize_address("0x1"),
+                module: "option".to_string(),
+                name: "Option".to_string(),
+                type_args: vec![FieldType::U128],
+            }))
+        );
+        assert_eq!(FieldType::parse("&mut T0").unwrap(), FieldType::Generic);
+        assert!(FieldType::parse("not_a_type").is_err());
+    }
+
+    #[test]
+    fn test_generate_message_board() {
+        let module = AbiModule::from_json(&message_board_abi()).unwrap();
+        let code = generate(&module, &["CreateMessageEvent".to_string()]).unwrap();
+
+        assert!(code.models.contains("pub struct MessageOnChain {\n    pub creator: String,\n    pub content: String,\n    pub creation_timestamp: String,"));
+        assert!(code.models.contains(
+            "    pub message: MessageOnChain,\n    pub reply_to: OptionOnChain<ObjectOnChain>,"
+        ));
+        assert!(code.models.contains("pub struct CreateMessageEvent {\n    pub txn_version: i64,\n    pub event_index: i64,\n    pub message_obj_addr: String,\n    pub message: serde_json::Value,\n    pub reply_to: Option<String>,\n}"));
+        assert!(code.models.contains("reply_to: self.reply_to.vec.first().cloned().map(|value| standardize_address(&value.inner)),"));
+        assert!(!code.models.contains("pub struct Message {"));
+        assert!(code
+            .up_sql
+            .contains("        message JSONB NOT NULL,\n        reply_to VARCHAR(300),\n"));
+        assert_eq!(
+            code.down_sql,
+            "-- This file should undo anything in `up.sql`\nDROP TABLE IF EXISTS create_message_events;"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use super::indexer_processor_config::IndexerProcessorConfig;
+use crate::health_check_server::HealthServerConfig;
+use serde::{Deserialize, Serialize};
+
+/// Top level of the config file. This takes the place of the server framework's
+/// GenericConfig, which starts its own probe server on `health_check_port`, so that
+/// `health_server_config` is the only thing that owns an HTTP listener.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "IndexerConfigFile")]
+pub struct IndexerConfig {
+    pub health_server_config: HealthServerConfig,
+    pub server_config: IndexerProcessorConfig,
+}
+
+/// The config file as written, which also accepts the `health_check_port` of configs
+/// written for the server framework's GenericConfig.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IndexerConfigFile {
+    #[serde(default)]
+    health_server_config: Option<HealthServerConfig>,
+    // Deprecated, same as `health_server_config.port`
+    #[serde(default)]
+    health_check_port: Option<u16>,
+    server_config: IndexerProcessorConfig,
+}
+
+impl TryFrom<IndexerConfigFile> for IndexerConfig {
+    type Error = String;
+
+    fn try_from(config: IndexerConfigFile) -> Result<Self, Self::Error> {
+        let health_server_config = match (config.health_server_config, config.health_check_port) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    "health_check_port and health_server_config can't both be set, \
+                    move health_check_port to health_server_config.port"
+                        .to_string(),
+                )
+            }
+            (None, Some(port)) => {
+                tracing::warn!(
+                    "health_check_port is deprecated, set health_server_config.port instead"
+                );
+                HealthServerConfig {
+                    port,
+                    ..HealthServerConfig::default()
+                }
+            }
+            (health_server_config, None) => health_server_config.unwrap_or_default(),
+        };
+        Ok(Self {
+            health_server_config,
+            server_config: config.server_config,
+        })
+    }
+}
@@ -1,5 +1,10 @@
-use super::processor_config::ProcessorConfig;
-use crate::steps::processor::ContractProcessor;
+use super::{
+    points_config::PointsConfig,
+    processor_config::ProcessorConfig,
+    transaction_source_config::{RecorderConfig, TransactionSourceConfig},
+    webhook_config::WebhookConfig,
+};
+use crate::steps::{event_handler::EventHandler, processor::ContractProcessor};
 use anyhow::Result;
 use aptos_indexer_processor_sdk::aptos_indexer_transaction_stream::TransactionStreamConfig;
 use aptos_indexer_processor_sdk_server_framework::RunnableConfig;
@@ -12,16 +17,45 @@ pub const QUERY_DEFAULT_RETRY_DELAY_MS: u64 = 500;
 #[serde(deny_unknown_fields)]
 pub struct IndexerProcessorConfig {
     pub processor_config: ProcessorConfig,
-    pub transaction_stream_config: TransactionStreamConfig,
+    // Required when transaction_source is grpc, the default
+    #[serde(default)]
+    pub transaction_stream_config: Option<TransactionStreamConfig>,
+    #[serde(default)]
+    pub transaction_source: TransactionSourceConfig,
+    // Archives every batch of transactions received when set
+    #[serde(default)]
+    pub recorder_config: Option<RecorderConfig>,
     pub db_config: DbConfig,
     pub contract_config: ContractConfig,
+    #[serde(default)]
+    pub readiness_config: ReadinessConfig,
+    #[serde(default)]
+    pub points_config: PointsConfig,
+    #[serde(default)]
+    pub leaderboard_config: LeaderboardConfig,
+    // Notifies webhooks of indexed changes when set
+    #[serde(default)]
+    pub webhook_config: Option<WebhookConfig>,
+}
+
+impl IndexerProcessorConfig {
+    /// Version to start from when the processor has no processor_status yet.
+    pub fn configured_starting_version(&self) -> Option<u64> {
+        match &self.transaction_source {
+            TransactionSourceConfig::Grpc => self
+                .transaction_stream_config
+                .as_ref()
+                .and_then(|config| config.starting_version),
+            TransactionSourceConfig::File(config) => config.starting_version,
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl RunnableConfig for IndexerProcessorConfig {
     async fn run(&self) -> Result<()> {
         match self.processor_config {
-            ProcessorConfig::ContractProcessor => {
+            ProcessorConfig::ContractProcessor(_) => {
                 let events_processor = ContractProcessor::new(self.clone()).await?;
                 events_processor.run_processor().await
             }
@@ -47,6 +81,9 @@ pub struct DbConfig {
     // Size of the pool for writes/reads to the DB. Limits maximum number of queries in flight
     #[serde(default = "DbConfig::default_db_pool_size")]
     pub db_pool_size: u32,
+    // Apply pending embedded migrations before the processor starts
+    #[serde(default)]
+    pub run_migrations_on_startup: bool,
 }
 
 impl DbConfig {
@@ -55,8 +92,202 @@ impl DbConfig {
     }
 }
 
+/// Thresholds for the readiness probe served by the health server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadinessConfig {
+    // Max age of processor_status.last_updated before the indexer is reported as not ready
+    #[serde(default = "ReadinessConfig::default_max_processor_status_age_secs")]
+    pub max_processor_status_age_secs: u64,
+    // Max time without a batch from the transaction stream before it's considered disconnected
+    #[serde(default = "ReadinessConfig::default_max_stream_idle_secs")]
+    pub max_stream_idle_secs: u64,
+}
+
+impl ReadinessConfig {
+    pub const fn default_max_processor_status_age_secs() -> u64 {
+        60
+    }
+
+    pub const fn default_max_stream_idle_secs() -> u64 {
+        30
+    }
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            max_processor_status_age_secs: Self::default_max_processor_status_age_secs(),
+            max_stream_idle_secs: Self::default_max_stream_idle_secs(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
+pub struct LeaderboardConfig {
+    // Length of the interval between rank snapshots, by block timestamp. 0 disables snapshots
+    #[serde(default = "LeaderboardConfig::default_snapshot_interval_secs")]
+    pub snapshot_interval_secs: u64,
+}
+
+impl LeaderboardConfig {
+    pub const fn default_snapshot_interval_secs() -> u64 {
+        // Daily
+        24 * 60 * 60
+    }
+}
+
+impl Default for LeaderboardConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval_secs: Self::default_snapshot_interval_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "ContractConfigFile")]
 pub struct ContractConfig {
-    pub contract_address: String,
+    // Addresses the contract is deployed at, e.g. a core package and a helper package
+    pub addresses: Vec<ContractAddressConfig>,
+    // Events to index under every address, each mapped to the handler that decodes and stores it
+    #[serde(default = "ContractConfig::default_events")]
+    pub events: Vec<EventConfig>,
+    // Also store every event emitted under the addresses in the generic events table, checked
+    // against the ABI of the module that emitted it
+    #[serde(default)]
+    pub capture_all_events: bool,
+}
+
+impl ContractConfig {
+    pub fn default_events() -> Vec<EventConfig> {
+        vec![
+            EventConfig {
+                event_type: "custom_indexer_ex_message_board::CreateMessageEvent".to_string(),
+                handler: EventHandler::CreateMessage,
+            },
+            EventConfig {
+                event_type: "custom_indexer_ex_message_board::UpdateMessageEvent".to_string(),
+                handler: EventHandler::UpdateMessage,
+            },
+        ]
+    }
+
+    /// Handlers of the configured events in the order they store a batch, see `EventHandler`.
+    pub fn event_handlers(&self) -> Vec<EventHandler> {
+        let mut handlers = self
+            .events
+            .iter()
+            .map(|event| event.handler)
+            .collect::<Vec<_>>();
+        handlers.sort();
+        handlers.dedup();
+        handlers
+    }
+}
+
+/// `contract_config` as written in the config file, which also accepts the single
+/// `contract_address` of configs written before multiple addresses could be indexed.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContractConfigFile {
+    #[serde(default)]
+    addresses: Option<Vec<ContractAddressConfig>>,
+    // Deprecated shorthand for `addresses: [{address: ...}]`, indexing every module
+    #[serde(default)]
+    contract_address: Option<String>,
+    #[serde(default = "ContractConfig::default_events")]
+    events: Vec<EventConfig>,
+    #[serde(default)]
+    capture_all_events: bool,
+}
+
+impl TryFrom<ContractConfigFile> for ContractConfig {
+    type Error = String;
+
+    fn try_from(config: ContractConfigFile) -> Result<Self, Self::Error> {
+        let addresses = match (config.addresses, config.contract_address) {
+            (Some(addresses), None) => addresses,
+            (None, Some(address)) => vec![ContractAddressConfig {
+                address,
+                modules: None,
+            }],
+            (Some(_), Some(_)) => {
+                return Err("contract_address and addresses can't both be set, move \
+                    contract_address to an entry of addresses"
+                    .to_string())
+            }
+            (None, None) => {
+                return Err("contract_config needs addresses, e.g. \
+                    addresses: [{address: \"0x...\"}]"
+                    .to_string())
+            }
+        };
+        Ok(Self {
+            addresses,
+            events: config.events,
+            capture_all_events: config.capture_all_events,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContractAddressConfig {
+    pub address: String,
+    // Modules to index events and upgrades from, all modules at the address when omitted
+    #[serde(default)]
+    pub modules: Option<Vec<String>>,
+}
+
+impl ContractAddressConfig {
+    pub fn includes_module(&self, module_name: &str) -> bool {
+        self.modules
+            .as_ref()
+            .map_or(true, |modules| modules.iter().any(|m| m == module_name))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventConfig {
+    // Event type under the contract addresses, in the form module::Struct
+    pub event_type: String,
+    pub handler: EventHandler,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contract_config: serde_json::Value) -> Result<ContractConfig, serde_json::Error> {
+        serde_json::from_value(contract_config)
+    }
+
+    #[test]
+    fn test_contract_address_shorthand() {
+        let config = parse(serde_json::json!({ "contract_address": "0x1" })).unwrap();
+        assert_eq!(config.addresses.len(), 1);
+        assert_eq!(config.addresses[0].address, "0x1");
+        assert!(config.addresses[0].modules.is_none());
+        assert_eq!(config.events.len(), 2);
+
+        let config = parse(serde_json::json!({
+            "addresses": [{ "address": "0x1", "modules": ["board"] }, { "address": "0x2" }],
+        }))
+        .unwrap();
+        assert_eq!(config.addresses.len(), 2);
+        assert!(config.addresses[0].includes_module("board"));
+        assert!(!config.addresses[0].includes_module("other"));
+
+        let error = parse(serde_json::json!({
+            "contract_address": "0x1",
+            "addresses": [{ "address": "0x2" }],
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("can't both be set"), "{}", error);
+        assert!(parse(serde_json::json!({})).is_err());
+        assert!(parse(serde_json::json!({ "contract_addresses": ["0x1"] })).is_err());
+    }
 }
@@ -1,2 +1,6 @@
+pub mod indexer_config;
 pub mod indexer_processor_config;
+pub mod points_config;
 pub mod processor_config;
+pub mod transaction_source_config;
+pub mod webhook_config;
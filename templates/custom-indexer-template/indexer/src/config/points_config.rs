@@ -0,0 +1,242 @@
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::steps::event_handler::EventHandler;
+
+/// Season whose points are also written to the `user_stats.s1_points` column, which predates
+/// configurable seasons.
+pub const LEGACY_S1_SEASON: &str = "s1";
+
+/// Rules for the points users earn from indexed events. Points are tracked per season in the
+/// `user_season_points` table, and `user_stats.total_points` is the sum across seasons.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PointsConfig {
+    pub seasons: Vec<SeasonConfig>,
+}
+
+impl Default for PointsConfig {
+    fn default() -> Self {
+        Self {
+            seasons: vec![SeasonConfig {
+                name: LEGACY_S1_SEASON.to_string(),
+                start_version: None,
+                end_version: None,
+                start_timestamp: None,
+                end_timestamp: None,
+                points: HashMap::from([
+                    (EventHandler::CreateMessage, 2),
+                    (EventHandler::UpdateMessage, 1),
+                ]),
+                multiplier: SeasonConfig::default_multiplier(),
+            }],
+        }
+    }
+}
+
+/// A season only counts events inside its window. Starts are inclusive, ends are exclusive and
+/// bounds that are omitted are open. Seasons may overlap, an event then earns points in each.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeasonConfig {
+    pub name: String,
+    #[serde(default)]
+    pub start_version: Option<i64>,
+    #[serde(default)]
+    pub end_version: Option<i64>,
+    // Unix timestamps in seconds, compared to the timestamp in the event
+    #[serde(default)]
+    pub start_timestamp: Option<i64>,
+    #[serde(default)]
+    pub end_timestamp: Option<i64>,
+    // Points per event, keyed by the handler the event is mapped to. Events of handlers that
+    // aren't listed earn no points
+    #[serde(default)]
+    pub points: HashMap<EventHandler, i64>,
+    // Applied to the points of every event in the season, the result is rounded
+    #[serde(default = "SeasonConfig::default_multiplier")]
+    pub multiplier: f64,
+}
+
+impl SeasonConfig {
+    pub const fn default_multiplier() -> f64 {
+        1.0
+    }
+
+    fn includes(&self, txn_version: i64, timestamp: i64) -> bool {
+        self.start_version.is_none_or(|start| txn_version >= start)
+            && self.end_version.is_none_or(|end| txn_version < end)
+            && self.start_timestamp.is_none_or(|start| timestamp >= start)
+            && self.end_timestamp.is_none_or(|end| timestamp < end)
+    }
+
+    fn points_for(&self, handler: EventHandler) -> i64 {
+        let points = self.points.get(&handler).copied().unwrap_or_default();
+        (points as f64 * self.multiplier).round() as i64
+    }
+}
+
+/// Points a single event earned, keyed by season. Stored with the event in the ledger.
+pub type SeasonPoints = BTreeMap<String, i64>;
+
+/// Points earned by a set of events.
+#[derive(Debug, Default)]
+pub struct UserPoints {
+    // Key is user address
+    // Value is (points across all seasons, points in the legacy s1 season)
+    pub totals: AHashMap<String, (i64, i64)>,
+    // Key is (user address, season)
+    pub seasons: AHashMap<(String, String), i64>,
+}
+
+impl PointsConfig {
+    /// Points an event earns in each season whose window includes it.
+    pub fn event_points(
+        &self,
+        handler: EventHandler,
+        txn_version: i64,
+        timestamp: i64,
+    ) -> SeasonPoints {
+        let mut season_points = SeasonPoints::new();
+        for season in &self.seasons {
+            if season.includes(txn_version, timestamp) {
+                *season_points.entry(season.name.clone()).or_default() +=
+                    season.points_for(handler);
+            }
+        }
+        season_points
+    }
+}
+
+impl UserPoints {
+    /// Sums the points of events given as (user address, points the event earned).
+    pub fn from_events(events: impl IntoIterator<Item = (String, SeasonPoints)>) -> Self {
+        let mut user_points = Self::default();
+        for (user_addr, season_points) in events {
+            let (total, s1) = user_points.totals.entry(user_addr.clone()).or_default();
+            for (season, points) in season_points {
+                *total += points;
+                if season == LEGACY_S1_SEASON {
+                    *s1 += points;
+                }
+                *user_points
+                    .seasons
+                    .entry((user_addr.clone(), season))
+                    .or_default() += points;
+            }
+        }
+        user_points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn season(name: &str, create_points: i64, multiplier: f64) -> SeasonConfig {
+        SeasonConfig {
+            name: name.to_string(),
+            start_version: None,
+            end_version: None,
+            start_timestamp: None,
+            end_timestamp: None,
+            points: HashMap::from([(EventHandler::CreateMessage, create_points)]),
+            multiplier,
+        }
+    }
+
+    #[test]
+    fn test_season_windows() {
+        let config = PointsConfig {
+            seasons: vec![SeasonConfig {
+                start_version: Some(100),
+                end_version: Some(200),
+                start_timestamp: Some(1_000),
+                end_timestamp: Some(2_000),
+                ..season("s2", 5, 1.0)
+            }],
+        };
+        let points = |txn_version, timestamp| {
+            config.event_points(EventHandler::CreateMessage, txn_version, timestamp)
+        };
+        // Starts are inclusive and ends exclusive
+        assert_eq!(
+            points(100, 1_000),
+            SeasonPoints::from([("s2".to_string(), 5)])
+        );
+        assert_eq!(
+            points(199, 1_999),
+            SeasonPoints::from([("s2".to_string(), 5)])
+        );
+        assert!(points(99, 1_500).is_empty());
+        assert!(points(200, 1_500).is_empty());
+        assert!(points(150, 999).is_empty());
+        assert!(points(150, 2_000).is_empty());
+        // Handlers that aren't listed earn nothing in the season
+        assert_eq!(
+            config.event_points(EventHandler::UpdateMessage, 150, 1_500),
+            SeasonPoints::from([("s2".to_string(), 0)])
+        );
+    }
+
+    #[test]
+    fn test_overlapping_seasons_and_multiplier() {
+        let config = PointsConfig {
+            seasons: vec![
+                season(LEGACY_S1_SEASON, 2, 1.0),
+                SeasonConfig {
+                    start_version: Some(150),
+                    ..season("boost", 3, 1.5)
+                },
+            ],
+        };
+        // 3 * 1.5 = 4.5 is rounded away from zero
+        assert_eq!(
+            config.event_points(EventHandler::CreateMessage, 150, 0),
+            SeasonPoints::from([("boost".to_string(), 5), (LEGACY_S1_SEASON.to_string(), 2)])
+        );
+
+        let user_points = UserPoints::from_events([
+            (
+                "0xa".to_string(),
+                config.event_points(EventHandler::CreateMessage, 100, 0),
+            ),
+            (
+                "0xa".to_string(),
+                config.event_points(EventHandler::CreateMessage, 150, 0),
+            ),
+            (
+                "0xb".to_string(),
+                config.event_points(EventHandler::UpdateMessage, 150, 0),
+            ),
+        ]);
+        // Totals sum every season, s1 also goes to the legacy s1_points column
+        assert_eq!(user_points.totals["0xa"], (9, 4));
+        assert_eq!(user_points.totals["0xb"], (0, 0));
+        assert_eq!(
+            user_points.seasons[&("0xa".to_string(), LEGACY_S1_SEASON.to_string())],
+            4
+        );
+        assert_eq!(
+            user_points.seasons[&("0xa".to_string(), "boost".to_string())],
+            5
+        );
+    }
+
+    #[test]
+    fn test_default_is_legacy_s1() {
+        let config = PointsConfig::default();
+        let user_points = UserPoints::from_events([
+            (
+                "0xa".to_string(),
+                config.event_points(EventHandler::CreateMessage, 1, 1),
+            ),
+            (
+                "0xa".to_string(),
+                config.event_points(EventHandler::UpdateMessage, 2, 2),
+            ),
+        ]);
+        assert_eq!(user_points.totals["0xa"], (3, 3));
+    }
+}
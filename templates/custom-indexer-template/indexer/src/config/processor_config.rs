@@ -32,7 +32,7 @@ use serde::{Deserialize, Serialize};
     strum(serialize_all = "snake_case")
 )]
 pub enum ProcessorConfig {
-    ContractProcessor,
+    ContractProcessor(ContractProcessorConfig),
 }
 
 impl ProcessorConfig {
@@ -42,6 +42,30 @@ impl ProcessorConfig {
         self.into()
     }
 }
+
+/// Configuration specific to the contract processor.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContractProcessorConfig {
+    #[serde(default)]
+    pub on_decode_error: DecodeErrorPolicy,
+}
+
+/// What the processor does with an event or change it fails to decode.
+/// Upgrades whose package registry doesn't match the modules written by the transaction
+/// are always recorded in the failed_items table.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodeErrorPolicy {
+    /// Stop processing, nothing after the item gets indexed until the issue is fixed.
+    #[default]
+    Halt,
+    /// Log the error and drop the item.
+    Skip,
+    /// Record the item in the failed_items table, it can be re-driven after a fix.
+    DeadLetter,
+}
+
 #[derive(Debug)]
 // To ensure that the variants of ProcessorConfig and Processor line up, in the testing
 // build path we derive EnumDiscriminants on this enum as well and make sure the two
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where the processor reads transactions from.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionSourceConfig {
+    /// The gRPC transaction stream configured in `transaction_stream_config`.
+    #[default]
+    Grpc,
+    /// Transaction files on local disk, for offline development and tests.
+    File(FileSourceConfig),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileSourceConfig {
+    // A file, or a directory whose files are read in file name order, e.g. a recorder archive.
+    // Files ending in .gz are decompressed
+    pub path: PathBuf,
+    pub format: TransactionFileFormat,
+    // At which tx version to start, versions before it are skipped
+    #[serde(default)]
+    pub starting_version: Option<u64>,
+    // At which tx version to stop, inclusive
+    #[serde(default)]
+    pub ending_version: Option<u64>,
+    // Max number of transactions per batch, like a response of the gRPC stream
+    #[serde(default = "FileSourceConfig::default_batch_size")]
+    pub batch_size: usize,
+    // Chain the transactions were recorded from. When set it's checked against the chain id
+    // in the DB, like the one reported by the gRPC stream
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+impl FileSourceConfig {
+    pub const fn default_batch_size() -> usize {
+        1000
+    }
+}
+
+/// Encoding of the transactions in a file. Transactions must be in version order, across
+/// files too.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionFileFormat {
+    /// One `aptos_protos` transaction per line, in protobuf JSON.
+    JsonLines,
+    /// Protobuf encoded transactions, each preceded by its length as a big-endian u32.
+    LengthPrefixedProtobuf,
+}
+
+/// Archives the transactions the processor receives, so the exact input of a run can be
+/// replayed with the `replay` command.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecorderConfig {
+    pub archive_dir: PathBuf,
+    // A new archive file is started once the current one reaches this compressed size
+    #[serde(default = "RecorderConfig::default_max_file_size_bytes")]
+    pub max_file_size_bytes: u64,
+    // The oldest archive files are deleted once all of them together exceed this size
+    #[serde(default = "RecorderConfig::default_max_total_size_bytes")]
+    pub max_total_size_bytes: u64,
+}
+
+impl RecorderConfig {
+    pub const fn default_max_file_size_bytes() -> u64 {
+        // 64 MiB
+        64 * 1024 * 1024
+    }
+
+    pub const fn default_max_total_size_bytes() -> u64 {
+        // 4 GiB
+        4 * 1024 * 1024 * 1024
+    }
+}
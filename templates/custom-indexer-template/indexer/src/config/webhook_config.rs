@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+
+use crate::db_models::outbox::NotificationKind;
+
+/// Webhooks notified of indexed changes. The storers write a notification per subscribed
+/// endpoint to the `outbox` table, in the same DB transaction as the change, and the delivery
+/// worker POSTs them to the endpoints.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub endpoints: Vec<WebhookEndpointConfig>,
+    // How often the outbox is checked for notifications that are due
+    #[serde(default = "WebhookConfig::default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    // Max number of notifications delivered at once
+    #[serde(default = "WebhookConfig::default_batch_size")]
+    pub batch_size: i64,
+    // A notification is marked failed after this many unsuccessful attempts
+    #[serde(default = "WebhookConfig::default_max_attempts")]
+    pub max_attempts: i32,
+    // Delay before the first retry, doubled after every failed attempt up to max_backoff_secs
+    #[serde(default = "WebhookConfig::default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "WebhookConfig::default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "WebhookConfig::default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+}
+
+impl WebhookConfig {
+    pub const fn default_poll_interval_ms() -> u64 {
+        1000
+    }
+
+    pub const fn default_batch_size() -> i64 {
+        100
+    }
+
+    pub const fn default_max_attempts() -> i32 {
+        10
+    }
+
+    pub const fn default_initial_backoff_ms() -> u64 {
+        1000
+    }
+
+    pub const fn default_max_backoff_secs() -> u64 {
+        // Hourly
+        60 * 60
+    }
+
+    pub const fn default_request_timeout_secs() -> u64 {
+        10
+    }
+
+    /// Delay before the next attempt of a notification that has failed `attempts` times.
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 31) as u32;
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(1 << exponent))
+            .min(Duration::from_secs(self.max_backoff_secs))
+    }
+
+    pub fn endpoint(&self, name: &str) -> Option<&WebhookEndpointConfig> {
+        self.endpoints.iter().find(|endpoint| endpoint.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpointConfig {
+    // Notifications are addressed to the endpoint by name, renaming it leaves the pending
+    // notifications undelivered
+    pub name: String,
+    pub url: Url,
+    // Key of the HMAC-SHA256 signature sent with every payload
+    pub secret: String,
+    // Kinds of notifications sent to the endpoint, all of them when omitted
+    #[serde(default)]
+    pub notifications: Option<Vec<NotificationKind>>,
+}
+
+impl WebhookEndpointConfig {
+    pub fn is_subscribed(&self, kind: NotificationKind) -> bool {
+        self.notifications
+            .as_ref()
+            .is_none_or(|notifications| notifications.contains(&kind))
+    }
+}
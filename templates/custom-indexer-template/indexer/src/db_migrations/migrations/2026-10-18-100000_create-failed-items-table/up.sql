@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE
+    failed_items (
+        txn_version BIGINT NOT NULL,
+        -- either 'event' or 'change'
+        item_type VARCHAR(20) NOT NULL,
+        -- index of the event, or of the write set change, within the transaction
+        item_index BIGINT NOT NULL,
+        type_str TEXT NOT NULL,
+        -- event data, or the upgrade related write set changes of the transaction as JSON
+        raw_data TEXT NOT NULL,
+        error TEXT NOT NULL,
+        retry_count BIGINT NOT NULL DEFAULT 0,
+        inserted_at TIMESTAMP NOT NULL DEFAULT NOW (),
+        PRIMARY KEY (txn_version, item_type, item_index)
+    );
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE IF EXISTS messages
+DROP COLUMN IF EXISTS contract_addr;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- address from contract_config that emitted the message events,
+-- rows indexed before this migration are left with an empty string
+ALTER TABLE IF EXISTS messages
+ADD COLUMN IF NOT EXISTS contract_addr VARCHAR(300) NOT NULL DEFAULT '';
+
+ALTER TABLE IF EXISTS messages
+ALTER COLUMN contract_addr
+DROP DEFAULT;
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE
+    event_ledger (
+        txn_version BIGINT NOT NULL,
+        -- index of the event within the transaction
+        event_index BIGINT NOT NULL,
+        -- handler that applied the event, e.g. 'create_message'
+        event_type VARCHAR(50) NOT NULL,
+        -- user whose stats the event counts towards
+        user_addr VARCHAR(300) NOT NULL,
+        event_timestamp BIGINT NOT NULL,
+        -- points the event earned in each season, e.g. {"s1": 2}, so a rewind takes back exactly
+        -- what was awarded even if points_config changed since
+        season_points JSONB NOT NULL,
+        inserted_at TIMESTAMP NOT NULL DEFAULT NOW (),
+        PRIMARY KEY (txn_version, event_index)
+    );
+
+CREATE INDEX event_ledger_user_addr_idx ON event_ledger (user_addr);
\ No newline at end of file
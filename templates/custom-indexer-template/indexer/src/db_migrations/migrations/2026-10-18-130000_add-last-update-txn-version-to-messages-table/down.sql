@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE IF EXISTS messages
+DROP COLUMN IF EXISTS last_update_txn_version;
\ No newline at end of file
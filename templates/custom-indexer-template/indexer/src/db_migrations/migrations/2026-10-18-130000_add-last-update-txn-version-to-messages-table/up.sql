@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- version of the transaction that emitted the event the message content comes from,
+-- (last_update_txn_version, last_update_event_idx) decides which update is the latest.
+ALTER TABLE IF EXISTS messages
+ADD COLUMN IF NOT EXISTS last_update_txn_version BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE IF EXISTS messages
+ALTER COLUMN last_update_txn_version
+DROP DEFAULT;
+
+-- Rows indexed before this migration don't record the version of their latest event. It's at
+-- or before the last version the processor completed, so they're backfilled with that version:
+-- replaying an older update leaves them alone, while any update after it replaces them.
+-- Limitation: an update replayed from that exact version is only compared by event index.
+UPDATE messages
+SET
+    last_update_txn_version = (
+        SELECT
+            COALESCE(MAX(last_success_version), 0)
+        FROM
+            processor_status
+    );
\ No newline at end of file
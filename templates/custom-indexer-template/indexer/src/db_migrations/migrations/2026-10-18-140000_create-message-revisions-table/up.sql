@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE
+    message_revisions (
+        message_obj_addr VARCHAR(300) NOT NULL,
+        -- position of the revision in (txn_version, event_index) order, 1 for the creation
+        revision_number BIGINT NOT NULL,
+        content TEXT NOT NULL,
+        -- only the creator can edit a message, enforced by the contract
+        editor_addr VARCHAR(300) NOT NULL,
+        txn_version BIGINT NOT NULL,
+        event_index BIGINT NOT NULL,
+        revision_timestamp BIGINT NOT NULL,
+        PRIMARY KEY (txn_version, event_index),
+        -- deferred, the storer renumbers revisions in the transaction that inserts them
+        UNIQUE (message_obj_addr, revision_number) DEFERRABLE INITIALLY DEFERRED
+    );
\ No newline at end of file
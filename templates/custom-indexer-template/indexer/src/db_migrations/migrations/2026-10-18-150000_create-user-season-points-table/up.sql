@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE
+    user_season_points (
+        user_addr VARCHAR(300) NOT NULL,
+        -- season name from points_config
+        season VARCHAR(100) NOT NULL,
+        points BIGINT NOT NULL,
+        PRIMARY KEY (user_addr, season)
+    );
+
+-- points indexed before seasons were configurable all belong to season 1
+INSERT INTO
+    user_season_points (user_addr, season, points)
+SELECT
+    user_addr,
+    's1',
+    s1_points
+FROM
+    user_stats
+WHERE
+    s1_points <> 0;
\ No newline at end of file
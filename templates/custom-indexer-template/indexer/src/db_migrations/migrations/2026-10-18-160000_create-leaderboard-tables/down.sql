@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS leaderboard_snapshots;
+
+DROP TABLE IF EXISTS leaderboard;
\ No newline at end of file
@@ -0,0 +1,39 @@
+-- Your SQL goes here
+CREATE TABLE
+    leaderboard (
+        user_addr VARCHAR(300) NOT NULL UNIQUE PRIMARY KEY,
+        -- user_stats.total_points
+        points BIGINT NOT NULL,
+        -- 1 + number of users with more points, users with the same points share a rank
+        rank BIGINT NOT NULL,
+        -- rank at the last snapshot, null for users that joined since
+        previous_rank BIGINT,
+        -- previous_rank - rank, positive when the user moved up
+        rank_delta BIGINT
+    );
+
+CREATE INDEX leaderboard_points_idx ON leaderboard (points);
+
+INSERT INTO
+    leaderboard (user_addr, points, rank)
+SELECT
+    user_addr,
+    total_points,
+    RANK() OVER (
+        ORDER BY
+            total_points DESC
+    )
+FROM
+    user_stats;
+
+CREATE TABLE
+    leaderboard_snapshots (
+        -- start of the snapshot interval, unix timestamp in seconds
+        snapshot_timestamp BIGINT NOT NULL,
+        user_addr VARCHAR(300) NOT NULL,
+        rank BIGINT NOT NULL,
+        points BIGINT NOT NULL,
+        -- last transaction version the ranks include, a rewind before it deletes the snapshot
+        txn_version BIGINT NOT NULL,
+        PRIMARY KEY (snapshot_timestamp, user_addr)
+    );
\ No newline at end of file
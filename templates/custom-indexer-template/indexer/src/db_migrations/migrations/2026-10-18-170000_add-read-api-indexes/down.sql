@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS leaderboard_rank_idx;
+DROP INDEX IF EXISTS messages_creator_addr_idx;
+DROP INDEX IF EXISTS messages_creation_timestamp_idx;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Keyset pagination of the read API walks these in order
+CREATE INDEX messages_creation_timestamp_idx ON messages (creation_timestamp, message_obj_addr);
+CREATE INDEX messages_creator_addr_idx ON messages (creator_addr, creation_timestamp, message_obj_addr);
+CREATE INDEX leaderboard_rank_idx ON leaderboard (rank, user_addr);
\ No newline at end of file
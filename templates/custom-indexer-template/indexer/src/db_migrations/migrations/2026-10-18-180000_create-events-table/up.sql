@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE
+    events (
+        txn_version BIGINT NOT NULL,
+        event_index BIGINT NOT NULL,
+        -- Address the emitting module is published at
+        account_address VARCHAR(300) NOT NULL,
+        module_name VARCHAR(300) NOT NULL,
+        struct_name VARCHAR(300) NOT NULL,
+        type_args TEXT[] NOT NULL,
+        data JSONB NOT NULL,
+        -- Upgrade of the module that was live at txn_version, NULL when its ABI is not indexed
+        upgrade_number BIGINT,
+        -- Why data does not match the event struct in the ABI, NULL when it matches or no ABI is indexed
+        abi_error TEXT,
+        PRIMARY KEY (txn_version, event_index)
+    );
+
+CREATE INDEX events_type_idx ON events (account_address, module_name, struct_name);
\ No newline at end of file
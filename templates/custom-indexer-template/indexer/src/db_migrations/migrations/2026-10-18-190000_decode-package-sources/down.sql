@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE package_upgrade_history
+DROP COLUMN IF EXISTS decoded_manifest,
+DROP COLUMN IF EXISTS source_available;
+
+ALTER TABLE module_upgrade_history
+DROP COLUMN IF EXISTS decoded_source,
+DROP COLUMN IF EXISTS source_available;
\ No newline at end of file
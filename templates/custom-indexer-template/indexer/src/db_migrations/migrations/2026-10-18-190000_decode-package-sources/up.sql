@@ -0,0 +1,37 @@
+-- Your SQL goes here
+-- Manifests and sources stay stored as the hex-encoded gzip blobs of 0x1::code::PackageRegistry,
+-- decoded copies are only filled for upgrades indexed from now on
+ALTER TABLE module_upgrade_history
+ADD COLUMN decoded_source TEXT,
+ADD COLUMN source_available BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Sources are 0x when the deployer published without them
+UPDATE module_upgrade_history
+SET
+    source_available = module_source_code NOT IN ('0x', '');
+
+ALTER TABLE module_upgrade_history
+ALTER COLUMN source_available
+DROP DEFAULT;
+
+ALTER TABLE package_upgrade_history
+ADD COLUMN decoded_manifest TEXT,
+ADD COLUMN source_available BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- A package and its modules are upgraded in the same transaction
+UPDATE package_upgrade_history p
+SET
+    source_available = EXISTS (
+        SELECT
+            1
+        FROM
+            module_upgrade_history m
+        WHERE
+            m.module_addr = p.package_addr
+            AND m.tx_version = p.tx_version
+            AND m.source_available
+    );
+
+ALTER TABLE package_upgrade_history
+ALTER COLUMN source_available
+DROP DEFAULT;
\ No newline at end of file
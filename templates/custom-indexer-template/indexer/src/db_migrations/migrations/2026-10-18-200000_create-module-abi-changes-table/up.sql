@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE
+    module_abi_changes (
+        module_addr VARCHAR(300) NOT NULL,
+        module_name VARCHAR(300) NOT NULL,
+        upgrade_number BIGINT NOT NULL,
+        -- order of the change within the diff against the previous upgrade
+        change_index BIGINT NOT NULL,
+        previous_upgrade_number BIGINT NOT NULL,
+        -- function or struct
+        item_kind VARCHAR(50) NOT NULL,
+        item_name VARCHAR(300) NOT NULL,
+        -- added, removed or changed
+        change_type VARCHAR(50) NOT NULL,
+        detail TEXT NOT NULL,
+        -- whether the change is allowed under the compatible upgrade policy
+        compatible BOOLEAN NOT NULL,
+        tx_version BIGINT NOT NULL,
+        PRIMARY KEY (
+            module_addr,
+            module_name,
+            upgrade_number,
+            change_index
+        )
+    );
\ No newline at end of file
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE
+    outbox (
+        id BIGSERIAL PRIMARY KEY,
+        -- name of the webhook endpoint in the config
+        endpoint VARCHAR(100) NOT NULL,
+        -- e.g. message_created, message_updated or package_upgraded
+        notification_kind VARCHAR(50) NOT NULL,
+        -- identifies the change notified, so storing a batch again notifies nothing new
+        notification_key VARCHAR(300) NOT NULL,
+        payload JSONB NOT NULL,
+        tx_version BIGINT NOT NULL,
+        -- pending, delivered or failed
+        status VARCHAR(20) NOT NULL DEFAULT 'pending',
+        attempts INT NOT NULL DEFAULT 0,
+        next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW (),
+        last_error TEXT,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW (),
+        delivered_at TIMESTAMP,
+        UNIQUE (endpoint, notification_key)
+    );
+
+CREATE INDEX outbox_pending_idx ON outbox (next_attempt_at)
+WHERE
+    status = 'pending';
\ No newline at end of file
@@ -1,11 +1,92 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    event_ledger (txn_version, event_index) {
+        txn_version -> Int8,
+        event_index -> Int8,
+        #[max_length = 50]
+        event_type -> Varchar,
+        #[max_length = 300]
+        user_addr -> Varchar,
+        event_timestamp -> Int8,
+        season_points -> Jsonb,
+        inserted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    events (txn_version, event_index) {
+        txn_version -> Int8,
+        event_index -> Int8,
+        #[max_length = 300]
+        account_address -> Varchar,
+        #[max_length = 300]
+        module_name -> Varchar,
+        #[max_length = 300]
+        struct_name -> Varchar,
+        type_args -> Array<Text>,
+        data -> Jsonb,
+        upgrade_number -> Nullable<Int8>,
+        abi_error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    failed_items (txn_version, item_type, item_index) {
+        txn_version -> Int8,
+        #[max_length = 20]
+        item_type -> Varchar,
+        item_index -> Int8,
+        type_str -> Text,
+        raw_data -> Text,
+        error -> Text,
+        retry_count -> Int8,
+        inserted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    leaderboard (user_addr) {
+        #[max_length = 300]
+        user_addr -> Varchar,
+        points -> Int8,
+        rank -> Int8,
+        previous_rank -> Nullable<Int8>,
+        rank_delta -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    leaderboard_snapshots (snapshot_timestamp, user_addr) {
+        snapshot_timestamp -> Int8,
+        #[max_length = 300]
+        user_addr -> Varchar,
+        rank -> Int8,
+        points -> Int8,
+        txn_version -> Int8,
+    }
+}
+
 diesel::table! {
     ledger_infos (chain_id) {
         chain_id -> Int8,
     }
 }
 
+diesel::table! {
+    message_revisions (txn_version, event_index) {
+        #[max_length = 300]
+        message_obj_addr -> Varchar,
+        revision_number -> Int8,
+        content -> Text,
+        #[max_length = 300]
+        editor_addr -> Varchar,
+        txn_version -> Int8,
+        event_index -> Int8,
+        revision_timestamp -> Int8,
+    }
+}
+
 diesel::table! {
     messages (message_obj_addr) {
         #[max_length = 300]
@@ -16,6 +97,30 @@ diesel::table! {
         last_update_timestamp -> Int8,
         last_update_event_idx -> Int8,
         content -> Text,
+        #[max_length = 300]
+        contract_addr -> Varchar,
+        last_update_txn_version -> Int8,
+    }
+}
+
+diesel::table! {
+    module_abi_changes (module_addr, module_name, upgrade_number, change_index) {
+        #[max_length = 300]
+        module_addr -> Varchar,
+        #[max_length = 300]
+        module_name -> Varchar,
+        upgrade_number -> Int8,
+        change_index -> Int8,
+        previous_upgrade_number -> Int8,
+        #[max_length = 50]
+        item_kind -> Varchar,
+        #[max_length = 300]
+        item_name -> Varchar,
+        #[max_length = 50]
+        change_type -> Varchar,
+        detail -> Text,
+        compatible -> Bool,
+        tx_version -> Int8,
     }
 }
 
@@ -30,6 +135,29 @@ diesel::table! {
         module_source_code -> Text,
         module_abi -> Json,
         tx_version -> Int8,
+        decoded_source -> Nullable<Text>,
+        source_available -> Bool,
+    }
+}
+
+diesel::table! {
+    outbox (id) {
+        id -> Int8,
+        #[max_length = 100]
+        endpoint -> Varchar,
+        #[max_length = 50]
+        notification_kind -> Varchar,
+        #[max_length = 300]
+        notification_key -> Varchar,
+        payload -> Jsonb,
+        tx_version -> Int8,
+        #[max_length = 20]
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
     }
 }
 
@@ -44,6 +172,8 @@ diesel::table! {
         package_manifest -> Text,
         source_digest -> Text,
         tx_version -> Int8,
+        decoded_manifest -> Nullable<Text>,
+        source_available -> Bool,
     }
 }
 
@@ -57,6 +187,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_season_points (user_addr, season) {
+        #[max_length = 300]
+        user_addr -> Varchar,
+        #[max_length = 100]
+        season -> Varchar,
+        points -> Int8,
+    }
+}
+
 diesel::table! {
     user_stats (user_addr) {
         #[max_length = 300]
@@ -71,10 +211,19 @@ diesel::table! {
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    event_ledger,
+    events,
+    failed_items,
+    leaderboard,
+    leaderboard_snapshots,
     ledger_infos,
+    message_revisions,
     messages,
+    module_abi_changes,
     module_upgrade_history,
+    outbox,
     package_upgrade_history,
     processor_status,
+    user_season_points,
     user_stats,
 );
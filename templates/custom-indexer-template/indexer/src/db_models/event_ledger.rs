@@ -0,0 +1,54 @@
+use diesel::{Insertable, QueryResult};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::points_config::SeasonPoints, schema::event_ledger, steps::event_handler::EventHandler,
+};
+
+#[derive(Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[diesel(table_name = event_ledger)]
+/// Database representation of an event that has been applied to user_stats.
+/// user_stats is only incremented for events that are new to the ledger, so
+/// reprocessing a version range doesn't count its events twice.
+pub struct EventLedgerEntry {
+    pub txn_version: i64,
+    pub event_index: i64,
+    pub event_type: String,
+    pub user_addr: String,
+    pub event_timestamp: i64,
+    // Points the event earned in each season, set by the storer with with_season_points
+    pub season_points: serde_json::Value,
+}
+
+impl EventLedgerEntry {
+    pub fn new(
+        txn_version: i64,
+        event_index: i64,
+        handler: EventHandler,
+        user_addr: &str,
+        event_timestamp: i64,
+    ) -> Self {
+        Self {
+            txn_version,
+            event_index,
+            event_type: handler.to_string(),
+            user_addr: user_addr.to_string(),
+            event_timestamp,
+            season_points: serde_json::json!({}),
+        }
+    }
+
+    pub fn with_season_points(self, season_points: &SeasonPoints) -> Self {
+        Self {
+            season_points: serde_json::json!(season_points),
+            ..self
+        }
+    }
+
+    /// Points stored in a ledger row, read back when they're counted or taken back.
+    pub fn parse_season_points(season_points: serde_json::Value) -> QueryResult<SeasonPoints> {
+        serde_json::from_value(season_points)
+            .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))
+    }
+}
@@ -0,0 +1,75 @@
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::{schema::failed_items, utils::database_utils::DbPoolConnection};
+
+/// Kind of item that failed to decode, stored in `failed_items.item_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum FailedItemType {
+    Event,
+    Change,
+}
+
+#[derive(Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[diesel(table_name = failed_items)]
+/// Database representation of an event or change that could not be decoded
+pub struct FailedItem {
+    pub txn_version: i64,
+    pub item_type: String,
+    pub item_index: i64,
+    pub type_str: String,
+    pub raw_data: String,
+    pub error: String,
+}
+
+impl FailedItem {
+    pub fn new(
+        txn_version: i64,
+        item_type: FailedItemType,
+        item_index: i64,
+        type_str: &str,
+        raw_data: String,
+        error: &anyhow::Error,
+    ) -> Self {
+        Self {
+            txn_version,
+            item_type: item_type.to_string(),
+            item_index,
+            type_str: type_str.to_string(),
+            raw_data,
+            // Alternate format includes the whole context chain
+            error: format!("{:#}", error),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Queryable)]
+#[diesel(table_name = failed_items)]
+/// Dead-lettered item as read back for re-driving
+pub struct FailedItemQuery {
+    pub txn_version: i64,
+    pub item_type: String,
+    pub item_index: i64,
+    pub type_str: String,
+    pub raw_data: String,
+    pub error: String,
+    pub retry_count: i64,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl FailedItemQuery {
+    /// All dead-lettered items in the order they were emitted on chain
+    pub async fn get_all(conn: &mut DbPoolConnection<'_>) -> diesel::QueryResult<Vec<Self>> {
+        failed_items::table
+            .order((
+                failed_items::txn_version.asc(),
+                failed_items::item_type.asc(),
+                failed_items::item_index.asc(),
+            ))
+            .load::<Self>(conn)
+            .await
+    }
+}
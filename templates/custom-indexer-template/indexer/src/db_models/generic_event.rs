@@ -0,0 +1,21 @@
+use diesel::{AsChangeset, Insertable};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::events;
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[diesel(table_name = events)]
+/// Database representation of any event emitted under a contract address, stored as is
+pub struct GenericEvent {
+    pub txn_version: i64,
+    pub event_index: i64,
+    pub account_address: String,
+    pub module_name: String,
+    pub struct_name: String,
+    pub type_args: Vec<String>,
+    pub data: serde_json::Value,
+    // Resolved against module_upgrade_history when the event is stored
+    pub upgrade_number: Option<i64>,
+    pub abi_error: Option<String>,
+}
@@ -0,0 +1,27 @@
+use diesel::{AsChangeset, Insertable, Queryable};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{leaderboard, leaderboard_snapshots};
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize, Queryable)]
+#[diesel(table_name = leaderboard)]
+/// Database representation of a user's position on the leaderboard
+pub struct LeaderboardEntry {
+    pub user_addr: String,
+    pub points: i64,
+    pub rank: i64,
+    pub previous_rank: Option<i64>,
+    pub rank_delta: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, FieldCount, Insertable, Serialize, Queryable)]
+#[diesel(table_name = leaderboard_snapshots)]
+/// Database representation of a user's position on the leaderboard at a snapshot
+pub struct LeaderboardSnapshot {
+    pub snapshot_timestamp: i64,
+    pub user_addr: String,
+    pub rank: i64,
+    pub points: i64,
+    pub txn_version: i64,
+}
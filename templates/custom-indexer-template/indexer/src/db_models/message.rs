@@ -1,11 +1,12 @@
+use anyhow::{Context, Result};
 use aptos_indexer_processor_sdk::utils::convert::standardize_address;
-use diesel::{AsChangeset, Insertable};
+use diesel::{AsChangeset, Insertable, Queryable};
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
 use crate::schema::messages;
 
-#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize, Queryable)]
 #[diesel(table_name = messages)]
 /// Database representation of a message
 pub struct Message {
@@ -15,6 +16,8 @@ pub struct Message {
     pub last_update_timestamp: i64,
     pub last_update_event_idx: i64,
     pub content: String,
+    pub contract_addr: String,
+    pub last_update_txn_version: i64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -34,16 +37,27 @@ pub struct CreateMessageEventOnChain {
 }
 
 impl CreateMessageEventOnChain {
-    pub fn to_db_message(&self) -> Message {
-        let creation_timestamp = self.message.creation_timestamp.parse().unwrap();
-        Message {
+    pub fn to_db_message(
+        &self,
+        contract_addr: &str,
+        txn_version: i64,
+        event_idx: i64,
+    ) -> Result<Message> {
+        let creation_timestamp = self
+            .message
+            .creation_timestamp
+            .parse()
+            .context("Invalid creation_timestamp")?;
+        Ok(Message {
             message_obj_addr: standardize_address(&self.message_obj_addr),
             creator_addr: standardize_address(self.message.creator.as_str()),
             creation_timestamp,
             content: self.message.content.clone(),
             last_update_timestamp: creation_timestamp,
-            last_update_event_idx: 0,
-        }
+            last_update_event_idx: event_idx,
+            contract_addr: contract_addr.to_string(),
+            last_update_txn_version: txn_version,
+        })
     }
 }
 
@@ -55,14 +69,29 @@ pub struct UpdateMessageEventOnChain {
 }
 
 impl UpdateMessageEventOnChain {
-    pub fn to_db_message(&self, last_update_event_idx: i64) -> Message {
-        Message {
+    pub fn to_db_message(
+        &self,
+        contract_addr: &str,
+        last_update_txn_version: i64,
+        last_update_event_idx: i64,
+    ) -> Result<Message> {
+        Ok(Message {
             message_obj_addr: standardize_address(&self.message_obj_addr),
             content: self.message.content.clone(),
             creator_addr: standardize_address(self.message.creator.as_str()),
-            creation_timestamp: self.message.creation_timestamp.parse().unwrap(),
-            last_update_timestamp: self.message.last_update_timestamp.parse().unwrap(),
+            creation_timestamp: self
+                .message
+                .creation_timestamp
+                .parse()
+                .context("Invalid creation_timestamp")?,
+            last_update_timestamp: self
+                .message
+                .last_update_timestamp
+                .parse()
+                .context("Invalid last_update_timestamp")?,
             last_update_event_idx,
-        }
+            contract_addr: contract_addr.to_string(),
+            last_update_txn_version,
+        })
     }
 }
@@ -0,0 +1,36 @@
+use diesel::{AsChangeset, Insertable, Queryable};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use super::message::Message;
+use crate::schema::message_revisions;
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Queryable, Serialize)]
+#[diesel(table_name = message_revisions)]
+/// Database representation of one revision of a message, from its create or update event
+pub struct MessageRevision {
+    pub message_obj_addr: String,
+    pub revision_number: i64,
+    pub content: String,
+    pub editor_addr: String,
+    pub txn_version: i64,
+    pub event_index: i64,
+    pub revision_timestamp: i64,
+}
+
+impl MessageRevision {
+    /// Builds the revision a create or update event produced. The revision number is
+    /// assigned when the revision is stored.
+    pub fn from_message(message: &Message) -> Self {
+        Self {
+            message_obj_addr: message.message_obj_addr.clone(),
+            revision_number: 0,
+            content: message.content.clone(),
+            // Only the creator can update a message
+            editor_addr: message.creator_addr.clone(),
+            txn_version: message.last_update_txn_version,
+            event_index: message.last_update_event_idx,
+            revision_timestamp: message.last_update_timestamp,
+        }
+    }
+}
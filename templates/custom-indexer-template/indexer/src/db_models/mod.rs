@@ -1,6 +1,14 @@
+pub mod event_ledger;
+pub mod failed_item;
+pub mod generic_event;
+pub mod leaderboard;
 pub mod ledger_info;
 pub mod message;
+pub mod message_revision;
+pub mod module_abi_change;
 pub mod module_upgrade;
+pub mod outbox;
 pub mod package_upgrade;
 pub mod processor_status;
+pub mod user_season_points;
 pub mod user_stat;
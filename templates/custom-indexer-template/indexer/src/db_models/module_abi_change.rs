@@ -0,0 +1,47 @@
+use diesel::{AsChangeset, Insertable};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::{schema::module_abi_changes, utils::move_abi::AbiChange};
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[diesel(table_name = module_abi_changes)]
+/// Database representation of a change to a module's ABI from one upgrade to the next
+pub struct ModuleAbiChange {
+    pub module_addr: String,
+    pub module_name: String,
+    pub upgrade_number: i64,
+    pub change_index: i64,
+    pub previous_upgrade_number: i64,
+    pub item_kind: String,
+    pub item_name: String,
+    pub change_type: String,
+    pub detail: String,
+    pub compatible: bool,
+    pub tx_version: i64,
+}
+
+impl ModuleAbiChange {
+    pub fn from_abi_change(
+        module_addr: &str,
+        module_name: &str,
+        (previous_upgrade_number, upgrade_number): (i64, i64),
+        change_index: usize,
+        abi_change: AbiChange,
+        tx_version: i64,
+    ) -> Self {
+        Self {
+            module_addr: module_addr.to_string(),
+            module_name: module_name.to_string(),
+            upgrade_number,
+            change_index: change_index as i64,
+            previous_upgrade_number,
+            item_kind: abi_change.item_kind.to_string(),
+            item_name: abi_change.item_name,
+            change_type: abi_change.change_type.to_string(),
+            detail: abi_change.detail,
+            compatible: abi_change.compatible,
+            tx_version,
+        }
+    }
+}
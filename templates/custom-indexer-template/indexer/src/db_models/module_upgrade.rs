@@ -12,7 +12,11 @@ pub struct ModuleUpgrade {
     pub module_name: String,
     pub upgrade_number: i64,
     pub module_bytecode: Vec<u8>,
+    // Hex-encoded gzip of the source, as stored on chain
     pub module_source_code: String,
     pub module_abi: serde_json::Value,
     pub tx_version: i64,
+    // None if the source wasn't published
+    pub decoded_source: Option<String>,
+    pub source_available: bool,
 }
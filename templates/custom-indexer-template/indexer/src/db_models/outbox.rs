@@ -0,0 +1,191 @@
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::webhook_config::WebhookConfig, db_models::message::Message, schema::outbox,
+    utils::database_utils::DbPoolConnection,
+};
+
+/// Kind of change a webhook is notified of, stored in `outbox.notification_kind`
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NotificationKind {
+    MessageCreated,
+    MessageUpdated,
+    PackageUpgraded,
+}
+
+/// Delivery status of a notification, stored in `outbox.status`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum OutboxStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, FieldCount, Insertable, Serialize)]
+#[diesel(table_name = outbox)]
+/// Database representation of a notification to deliver to a webhook endpoint
+pub struct OutboxEntry {
+    pub endpoint: String,
+    pub notification_kind: String,
+    pub notification_key: String,
+    pub payload: serde_json::Value,
+    pub tx_version: i64,
+}
+
+impl OutboxEntry {
+    /// The notification of a change for each endpoint subscribed to its kind. The key
+    /// identifies the change, receivers can use it to drop duplicates.
+    pub fn for_subscribers(
+        webhook_config: &WebhookConfig,
+        kind: NotificationKind,
+        notification_key: String,
+        tx_version: i64,
+        data: serde_json::Value,
+    ) -> Vec<Self> {
+        let payload = serde_json::json!({
+            "kind": kind,
+            "key": notification_key,
+            "tx_version": tx_version,
+            "data": data,
+        });
+        webhook_config
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_subscribed(kind))
+            .map(|endpoint| Self {
+                endpoint: endpoint.name.clone(),
+                notification_kind: kind.to_string(),
+                notification_key: notification_key.clone(),
+                payload: payload.clone(),
+                tx_version,
+            })
+            .collect()
+    }
+
+    /// Notifications of messages written by a create or update event. An update is keyed
+    /// by the event, so every update of a message is notified.
+    pub fn for_messages(
+        webhook_config: &WebhookConfig,
+        kind: NotificationKind,
+        messages: &[Message],
+    ) -> Vec<Self> {
+        messages
+            .iter()
+            .flat_map(|message| {
+                let notification_key = match kind {
+                    NotificationKind::MessageUpdated => format!(
+                        "{}:{}:{}:{}",
+                        kind,
+                        message.message_obj_addr,
+                        message.last_update_txn_version,
+                        message.last_update_event_idx
+                    ),
+                    _ => format!("{}:{}", kind, message.message_obj_addr),
+                };
+                Self::for_subscribers(
+                    webhook_config,
+                    kind,
+                    notification_key,
+                    message.last_update_txn_version,
+                    serde_json::json!(message),
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Queryable)]
+#[diesel(table_name = outbox)]
+/// Pending notification as read back for delivery
+pub struct OutboxNotification {
+    pub id: i64,
+    pub endpoint: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+impl OutboxNotification {
+    /// Claims the pending notifications whose next attempt is due, oldest first, by moving
+    /// their next attempt `lease` later. Rows another instance is claiming are skipped, so
+    /// several instances can deliver from the same outbox without sending a notification twice.
+    /// A claim that isn't followed by mark_delivered or mark_attempt_failed, e.g. when the
+    /// instance stops, expires with the lease and the notification is attempted again.
+    pub async fn get_due(
+        conn: &mut DbPoolConnection<'_>,
+        limit: i64,
+        lease: chrono::Duration,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        conn.transaction(async move |conn| {
+            let notifications = outbox::table
+                .filter(outbox::status.eq(OutboxStatus::Pending.to_string()))
+                .filter(outbox::next_attempt_at.le(diesel::dsl::now))
+                .order(outbox::id.asc())
+                .limit(limit)
+                .select((
+                    outbox::id,
+                    outbox::endpoint,
+                    outbox::payload,
+                    outbox::attempts,
+                ))
+                .for_update()
+                .skip_locked()
+                .load::<Self>(conn)
+                .await?;
+            diesel::update(outbox::table.filter(
+                outbox::id.eq_any(notifications.iter().map(|notification| notification.id)),
+            ))
+            .set(outbox::next_attempt_at.eq(chrono::Utc::now().naive_utc() + lease))
+            .execute(conn)
+            .await?;
+            Ok(notifications)
+        })
+        .await
+    }
+
+    pub async fn mark_delivered(&self, conn: &mut DbPoolConnection<'_>) -> diesel::QueryResult<()> {
+        diesel::update(outbox::table.find(self.id))
+            .set((
+                outbox::status.eq(OutboxStatus::Delivered.to_string()),
+                outbox::attempts.eq(self.attempts + 1),
+                outbox::last_error.eq(None::<String>),
+                outbox::delivered_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt, the notification is retried at `next_attempt_at` or marked
+    /// failed when there is none.
+    pub async fn mark_attempt_failed(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+        error: &str,
+        next_attempt_at: Option<chrono::NaiveDateTime>,
+    ) -> diesel::QueryResult<()> {
+        let status = match next_attempt_at {
+            Some(_) => OutboxStatus::Pending,
+            None => OutboxStatus::Failed,
+        };
+        diesel::update(outbox::table.find(self.id))
+            .set((
+                outbox::status.eq(status.to_string()),
+                outbox::attempts.eq(self.attempts + 1),
+                outbox::last_error.eq(error),
+                // Failed notifications keep the time of their last attempt
+                outbox::next_attempt_at
+                    .eq(next_attempt_at.unwrap_or_else(|| chrono::Utc::now().naive_utc())),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+use anyhow::{Context, Result};
 use diesel::{AsChangeset, Insertable};
 use field_count::FieldCount;
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
+use std::io::Read;
 
 use crate::schema::package_upgrade_history;
 
@@ -12,9 +15,39 @@ pub struct PackageUpgrade {
     pub package_name: String,
     pub upgrade_number: i64,
     pub upgrade_policy: i64,
+    // Hex-encoded gzip of Move.toml, as stored on chain
     pub package_manifest: String,
     pub source_digest: String,
     pub tx_version: i64,
+    // Move.toml, None if the manifest wasn't published
+    pub decoded_manifest: Option<String>,
+    // Whether the source of any module was published
+    pub source_available: bool,
+}
+
+/// Decodes a hex-encoded gzip blob of 0x1::code::PackageRegistry, i.e. a package manifest or
+/// a module's source. Returns None for `0x`, stored when it wasn't published.
+pub fn decode_package_blob(blob: &str) -> Result<Option<String>> {
+    let hex = blob.strip_prefix("0x").unwrap_or(blob);
+    if hex.is_empty() {
+        return Ok(None);
+    }
+    let bytes = hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .context("Invalid hex")?;
+    let mut decoded = String::new();
+    GzDecoder::new(bytes.as_slice())
+        .read_to_string(&mut decoded)
+        .context("Invalid gzip")?;
+    Ok(Some(decoded))
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -24,6 +57,18 @@ pub struct ModuleOnChain {
     pub source: String,
 }
 
+impl ModuleOnChain {
+    pub fn source_available(&self) -> bool {
+        !self.source.trim_start_matches("0x").is_empty()
+    }
+
+    /// Source of the module, None if it wasn't published.
+    pub fn decode_source(&self) -> Result<Option<String>> {
+        decode_package_blob(&self.source)
+            .with_context(|| format!("Failed to decode the source of module {}", self.name))
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct UpgradePolicyOnChain {
     pub policy: i64,
@@ -39,6 +84,34 @@ pub struct PackageOnChain {
     pub upgrade_policy: UpgradePolicyOnChain,
 }
 
+impl PackageOnChain {
+    pub fn parse_upgrade_number(&self) -> Result<i64> {
+        self.upgrade_number
+            .parse()
+            .with_context(|| format!("Invalid upgrade_number for package {}", self.name))
+    }
+
+    pub fn to_db_package_upgrade(
+        &self,
+        tx_version: i64,
+        package_addr: String,
+    ) -> Result<PackageUpgrade> {
+        Ok(PackageUpgrade {
+            package_addr,
+            package_name: self.name.clone(),
+            upgrade_number: self.parse_upgrade_number()?,
+            upgrade_policy: self.upgrade_policy.policy,
+            package_manifest: self.manifest.clone(),
+            source_digest: self.source_digest.clone(),
+            tx_version,
+            decoded_manifest: decode_package_blob(&self.manifest).with_context(|| {
+                format!("Failed to decode the manifest of package {}", self.name)
+            })?,
+            source_available: self.modules.iter().any(ModuleOnChain::source_available),
+        })
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 /// On-chain representation of a write module change
 pub struct PackageUpgradeChangeOnChain {
@@ -50,18 +123,55 @@ impl PackageUpgradeChangeOnChain {
         &self,
         tx_version: i64,
         package_addr: String,
-    ) -> Vec<PackageUpgrade> {
+    ) -> Result<Vec<PackageUpgrade>> {
         self.packages
             .iter()
-            .map(|package| PackageUpgrade {
-                package_addr: package_addr.clone(),
-                package_name: package.name.clone(),
-                upgrade_number: package.upgrade_number.parse().unwrap(),
-                upgrade_policy: package.upgrade_policy.policy,
-                package_manifest: package.manifest.clone(),
-                source_digest: package.source_digest.clone(),
-                tx_version,
-            })
+            .map(|package| package.to_db_package_upgrade(tx_version, package_addr.clone()))
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn gzip_hex(contents: &str) -> String {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder
+            .finish()
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_package_blob() {
+        let source = "module 0xcafe::message_board {}\n";
+        let hex = gzip_hex(source);
+        assert_eq!(
+            decode_package_blob(&format!("0x{}", hex))
+                .unwrap()
+                .as_deref(),
+            Some(source)
+        );
+        assert_eq!(
+            decode_package_blob(&hex.to_uppercase()).unwrap().as_deref(),
+            Some(source)
+        );
+
+        // Not published
+        assert_eq!(decode_package_blob("0x").unwrap(), None);
+        assert_eq!(decode_package_blob("").unwrap(), None);
+
+        let error = decode_package_blob(&format!("0x{}0", hex)).unwrap_err();
+        assert!(error.to_string().contains("Invalid hex"), "{:#}", error);
+        let error = decode_package_blob("0xzz").unwrap_err();
+        assert!(error.to_string().contains("Invalid hex"), "{:#}", error);
+        let error = decode_package_blob("0x0102").unwrap_err();
+        assert!(error.to_string().contains("Invalid gzip"), "{:#}", error);
+    }
+}
@@ -1,5 +1,6 @@
 use diesel::{AsChangeset, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable};
 use diesel_async::RunQueryDsl;
+use serde::Serialize;
 
 use crate::{schema::processor_status, utils::database_utils::DbPoolConnection};
 
@@ -12,7 +13,7 @@ pub struct ProcessorStatus {
     pub last_transaction_timestamp: Option<chrono::NaiveDateTime>,
 }
 
-#[derive(AsChangeset, Debug, Queryable)]
+#[derive(AsChangeset, Debug, Queryable, Serialize)]
 #[diesel(table_name = processor_status)]
 /// Only tracking the latest version successfully processed
 pub struct ProcessorStatusQuery {
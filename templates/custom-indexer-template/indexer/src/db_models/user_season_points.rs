@@ -0,0 +1,14 @@
+use diesel::{AsChangeset, Insertable, Queryable};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::user_season_points;
+
+#[derive(AsChangeset, Clone, Debug, Deserialize, FieldCount, Insertable, Serialize, Queryable)]
+#[diesel(table_name = user_season_points)]
+/// Database representation of the points a user earned in a season
+pub struct UserSeasonPoints {
+    pub user_addr: String,
+    pub season: String,
+    pub points: i64,
+}
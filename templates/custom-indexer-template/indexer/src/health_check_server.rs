@@ -1,41 +1,167 @@
-//! This contains the health server, a basic server that for now always returns 200.
-//! This is necessary to run the processor in Cloud Run, which expects to be able to
-//! query a HTTP server to check for liveness.
+//! This contains the HTTP server, the single listener for the indexer's operational
+//! endpoints, configured by the `health_server_config` section of the config.
+//! `/healthz` always returns 200 while the process is up, which is what Cloud Run and
+//! Kubernetes liveness probes need. `/readyz` returns 503 with a JSON reason unless the
+//! DB is reachable, `processor_status` has been updated recently and the transaction
+//! stream is delivering batches. `/metrics` serves the indexer's metrics in Prometheus
+//! text format. The read API in `read_api` is served from the same listener.
 
 use anyhow::{Context, Result};
 use poem::{
-    get, handler, http::Method, listener::TcpListener, middleware::Cors, EndpointExt, Route, Server,
+    get, handler,
+    http::{HeaderValue, Method, StatusCode},
+    listener::{BoxListener, Listener, RustlsCertificate, RustlsConfig, TcpListener},
+    middleware::Cors,
+    web::Json,
+    EndpointExt, IntoResponse, Response, Route, Server,
 };
+use prometheus::{Encoder, TextEncoder};
 use serde::{Deserialize, Serialize};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use crate::{read_api, utils::pipeline_state::PIPELINE_STATE};
 
-/// This configures the health server.
+/// This configures the HTTP listener that serves every operational endpoint.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct HealthServerConfig {
-    pub listen_address: SocketAddrV4,
+    pub bind_address: IpAddr,
+    pub port: u16,
+    // Origins allowed to make cross-origin requests, any origin is allowed when empty
+    pub cors_allowed_origins: Vec<String>,
+    // Serve over HTTPS instead of plain HTTP when set
+    pub tls: Option<TlsConfig>,
 }
 
 impl Default for HealthServerConfig {
     fn default() -> Self {
         Self {
-            listen_address: SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 8080),
+            bind_address: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            port: 8080,
+            cors_allowed_origins: vec![],
+            tls: None,
+        }
+    }
+}
+
+/// PEM encoded certificate chain and private key for the HTTP listener.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl HealthServerConfig {
+    pub fn listen_address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    fn cors(&self) -> Result<Cors> {
+        let origins = self
+            .cors_allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::try_from(origin.as_str())
+                    .with_context(|| format!("Invalid CORS origin {:?}", origin))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Cors::new()
+            .allow_methods(vec![Method::GET, Method::POST])
+            .allow_origins(origins))
+    }
+
+    fn listener(&self) -> Result<BoxListener> {
+        let listener = TcpListener::bind(self.listen_address());
+        match &self.tls {
+            Some(tls) => {
+                let cert = std::fs::read(&tls.cert_path).with_context(|| {
+                    format!("Failed to read TLS certificate {}", tls.cert_path.display())
+                })?;
+                let key = std::fs::read(&tls.key_path).with_context(|| {
+                    format!("Failed to read TLS private key {}", tls.key_path.display())
+                })?;
+                let rustls_config =
+                    RustlsConfig::new().fallback(RustlsCertificate::new().cert(cert).key(key));
+                Ok(listener.rustls(rustls_config).boxed())
+            }
+            None => Ok(listener.boxed()),
         }
     }
 }
 
 pub async fn run(config: HealthServerConfig) -> Result<()> {
-    tracing::info!("Health server starting at {}", config.listen_address);
-    let cors = Cors::new().allow_methods(vec![Method::GET, Method::POST]);
-    let route = Route::new().nest("/", get(root)).with(cors);
-    Server::new(TcpListener::bind(config.listen_address))
+    let route = Route::new()
+        .at("/healthz", get(healthz))
+        .at("/readyz", get(readyz))
+        .at("/metrics", get(metrics))
+        .at("/messages", get(read_api::list_messages))
+        .at("/messages/:obj_addr", get(read_api::get_message))
+        .at("/users/:addr/stats", get(read_api::get_user_stats))
+        .at("/leaderboard", get(read_api::list_leaderboard))
+        .at("/status", get(read_api::get_status))
+        .at("/stream/messages", get(read_api::stream_messages))
+        .at("/stream/users/:addr", get(read_api::stream_user))
+        .nest("/", get(root))
+        .with(config.cors()?);
+    // Bind before serving so a port that's already taken fails startup instead of the
+    // error surfacing later from the server task
+    let acceptor = config.listener()?.into_acceptor().await.with_context(|| {
+        format!(
+            "Failed to bind the HTTP server to {}, is another process using the port?",
+            config.listen_address()
+        )
+    })?;
+    tracing::info!(
+        "HTTP server listening at {} ({})",
+        config.listen_address(),
+        if config.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        }
+    );
+    Server::new_with_acceptor(acceptor)
         .name("health-server")
         .run(route)
         .await
-        .context("Health server stopped running unexpectedly")
+        .context("HTTP server stopped running unexpectedly")
 }
 
 #[handler]
 async fn root() -> String {
     "Hello from the root!!".to_string()
 }
+
+#[handler]
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[handler]
+async fn readyz() -> Response {
+    let report = PIPELINE_STATE.check_readiness().await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Json(report).with_status(status).into_response()
+}
+
+#[handler]
+async fn metrics() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => Response::builder()
+            .content_type(encoder.format_type())
+            .body(buffer),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(format!("Failed to encode metrics: {}", e)),
+    }
+}
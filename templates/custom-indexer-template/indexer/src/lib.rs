@@ -1,8 +1,11 @@
+pub mod codegen;
 pub mod config;
 pub mod db_models;
 pub mod health_check_server;
+pub mod read_api;
 pub mod steps;
 pub mod utils;
+pub mod webhook_delivery;
 
 #[path = "db_migrations/schema.rs"]
 pub mod schema;
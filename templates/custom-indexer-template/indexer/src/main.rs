@@ -1,26 +1,207 @@
-use anyhow::Result;
-use aptos_indexer_processor_sdk_server_framework::ServerArgs;
-use clap::Parser;
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk_server_framework::{
+    load, setup_logging, setup_panic_handler, RunnableConfig,
+};
+use clap::{Parser, Subcommand};
 use indexer::{
-    config::indexer_processor_config::IndexerProcessorConfig,
-    health_check_server::{self, HealthServerConfig},
+    codegen::{generate, load_module_abi, write_generated, AbiModule},
+    config::{
+        indexer_config::IndexerConfig,
+        transaction_source_config::{
+            FileSourceConfig, TransactionFileFormat, TransactionSourceConfig,
+        },
+    },
+    health_check_server,
+    steps::redrive::redrive_failed_items,
+    utils::{
+        database_migrations::{migration_status, revert_migrations, run_pending_migrations},
+        rewind::rewind_to_version,
+    },
 };
+use std::path::PathBuf;
 
 #[cfg(unix)]
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-async fn run_health_server() -> Result<()> {
-    health_check_server::run(HealthServerConfig::default()).await
+#[derive(Debug, Parser)]
+struct Args {
+    #[clap(short, long, value_parser)]
+    config_path: PathBuf,
+    /// Runs the indexer when omitted
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the database migrations embedded in this binary
+    Migrate {
+        #[clap(subcommand)]
+        action: MigrateAction,
+    },
+    /// Decode and store the items recorded in the failed_items table again
+    Redrive,
+    /// Roll the indexed data back to right after a transaction version, the processor then
+    /// resumes from the next version
+    Rewind {
+        #[clap(long)]
+        to_version: i64,
+        /// Print how many rows would change without changing anything
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Run the processor over transactions archived by the recorder instead of the gRPC
+    /// stream. Like a normal run, it resumes after the version in processor_status, so replay
+    /// into a fresh database or rewind first
+    Replay {
+        /// Archive directory, or a single archive file
+        #[clap(long)]
+        archive: PathBuf,
+        #[clap(long)]
+        starting_version: Option<u64>,
+        /// Stop after this version, inclusive
+        #[clap(long)]
+        ending_version: Option<u64>,
+    },
+    /// Generate on-chain structs, Diesel models and a migration for the structs of a Move
+    /// module from its ABI, to review and copy into the indexer
+    Codegen(CodegenArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CodegenArgs {
+    /// ABI JSON file, the REST API's response for the module or its `abi`. When omitted
+    /// the ABI is read from module_upgrade_history
+    #[clap(long)]
+    abi: Option<PathBuf>,
+    #[clap(long)]
+    module_addr: Option<String>,
+    #[clap(long)]
+    module_name: Option<String>,
+    /// Upgrade of the module to read from module_upgrade_history, the latest by default
+    #[clap(long)]
+    upgrade_number: Option<i64>,
+    /// Only generate models for these structs, all structs by default
+    #[clap(long, value_delimiter = ',')]
+    structs: Vec<String>,
+    #[clap(long, default_value = "generated")]
+    out_dir: PathBuf,
 }
 
-async fn run_indexer() -> Result<()> {
-    ServerArgs::parse()
-        .run::<IndexerProcessorConfig>(tokio::runtime::Handle::current())
-        .await
+#[derive(Debug, Subcommand)]
+enum MigrateAction {
+    /// Apply all pending migrations
+    Up,
+    /// Revert the most recently applied migrations
+    Down {
+        #[clap(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List every migration and whether it has been applied
+    Status,
+}
+
+async fn run_indexer(config_path: PathBuf) -> Result<()> {
+    setup_logging();
+    setup_panic_handler();
+    let config = load::<IndexerConfig>(&config_path)?;
+    // Fails immediately if the HTTP server can't bind, which stops the processor too
+    tokio::try_join!(
+        health_check_server::run(config.health_server_config),
+        config.server_config.run(),
+    )?;
+    Ok(())
+}
+
+async fn run_migrate(config_path: PathBuf, action: MigrateAction) -> Result<()> {
+    setup_logging();
+    let config = load::<IndexerConfig>(&config_path)?;
+    let db_config = config.server_config.db_config;
+    match action {
+        MigrateAction::Up => run_pending_migrations(&db_config).await,
+        MigrateAction::Down { steps } => revert_migrations(&db_config, steps).await,
+        MigrateAction::Status => {
+            for (name, applied) in migration_status(&db_config).await? {
+                println!("[{}] {}", if applied { "X" } else { " " }, name);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn run_redrive(config_path: PathBuf) -> Result<()> {
+    setup_logging();
+    let config = load::<IndexerConfig>(&config_path)?;
+    redrive_failed_items(&config.server_config).await
+}
+
+async fn run_rewind(config_path: PathBuf, to_version: i64, dry_run: bool) -> Result<()> {
+    setup_logging();
+    let config = load::<IndexerConfig>(&config_path)?;
+    let summary = rewind_to_version(&config.server_config, to_version, dry_run).await?;
+    if dry_run {
+        println!("Dry run, rewinding to version {} would change:", to_version);
+    } else {
+        println!("Rewound to version {}:", to_version);
+    }
+    for (change, rows) in summary.changes {
+        println!("  {}: {}", change, rows);
+    }
+    Ok(())
+}
+
+async fn run_replay(
+    config_path: PathBuf,
+    archive: PathBuf,
+    starting_version: Option<u64>,
+    ending_version: Option<u64>,
+) -> Result<()> {
+    setup_logging();
+    setup_panic_handler();
+    let mut config = load::<IndexerConfig>(&config_path)?.server_config;
+    config.transaction_source = TransactionSourceConfig::File(FileSourceConfig {
+        path: archive,
+        format: TransactionFileFormat::LengthPrefixedProtobuf,
+        starting_version,
+        ending_version,
+        batch_size: FileSourceConfig::default_batch_size(),
+        chain_id: None,
+    });
+    // Don't archive the replayed transactions again
+    config.recorder_config = None;
+    config.run().await
+}
+
+async fn run_codegen(config_path: PathBuf, args: CodegenArgs) -> Result<()> {
+    let abi = match (args.abi, args.module_addr, args.module_name) {
+        (Some(abi), _, _) => serde_json::from_str(
+            &std::fs::read_to_string(&abi)
+                .with_context(|| format!("Failed to read {}", abi.display()))?,
+        )
+        .with_context(|| format!("Failed to parse {}", abi.display()))?,
+        (None, Some(module_addr), Some(module_name)) => {
+            let config = load::<IndexerConfig>(&config_path)?;
+            load_module_abi(
+                &config.server_config.db_config,
+                &module_addr,
+                &module_name,
+                args.upgrade_number,
+            )
+            .await?
+        }
+        _ => anyhow::bail!("Either --abi or both --module-addr and --module-name are required"),
+    };
+    let module = AbiModule::from_json(&abi)?;
+    let code = generate(&module, &args.structs)?;
+    for path in write_generated(&args.out_dir, &module, &code)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse();
     let num_cpus = num_cpus::get();
     let worker_threads = (num_cpus).max(16);
 
@@ -32,7 +213,22 @@ fn main() -> Result<()> {
         .build()
         .unwrap()
         .block_on(async {
-            tokio::try_join!(run_health_server(), run_indexer())?;
-            Ok(())
+            match args.command {
+                None => run_indexer(args.config_path).await,
+                Some(Command::Migrate { action }) => run_migrate(args.config_path, action).await,
+                Some(Command::Redrive) => run_redrive(args.config_path).await,
+                Some(Command::Rewind {
+                    to_version,
+                    dry_run,
+                }) => run_rewind(args.config_path, to_version, dry_run).await,
+                Some(Command::Replay {
+                    archive,
+                    starting_version,
+                    ending_version,
+                }) => run_replay(args.config_path, archive, starting_version, ending_version).await,
+                Some(Command::Codegen(codegen_args)) => {
+                    run_codegen(args.config_path, codegen_args).await
+                }
+            }
         })
 }
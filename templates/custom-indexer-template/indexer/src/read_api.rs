@@ -0,0 +1,465 @@
+//! Read-only REST endpoints over the indexed data, served by the HTTP server next to the
+//! operational endpoints so clients don't need DB credentials. Responses are the
+//! `db_models` structs serialized as JSON. Lists use keyset pagination: a page carries a
+//! `next_cursor` to pass back as `cursor` for the next page, until it's null.
+//!
+//! The stream endpoints push messages, and a user's stats, as they are indexed, over
+//! Server-Sent Events. Message events carry the version of their last update as the event id,
+//! so a reconnecting `EventSource` resumes with `Last-Event-ID`, and other clients can pass
+//! `since_version`. Resuming replays the messages updated from that version on, inclusive, so
+//! a message can be sent twice: clients drop duplicates by address and version.
+
+use ahash::AHashSet;
+use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use futures_util::{stream, Stream, StreamExt};
+use poem::{
+    error::ResponseError,
+    handler,
+    http::{HeaderMap, StatusCode},
+    web::{
+        sse::{Event, SSE},
+        Json, Path, Query,
+    },
+    IntoResponse, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::{
+    db_models::{
+        leaderboard::LeaderboardEntry, message::Message, processor_status::ProcessorStatusQuery,
+        user_stat::UserStat,
+    },
+    schema::{leaderboard, messages, processor_status, user_stats},
+    utils::{
+        database_utils::{ArcDbPool, DbPoolConnection},
+        live_updates::{LiveUpdate, LIVE_UPDATES},
+        pipeline_state::PIPELINE_STATE,
+    },
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 25;
+const MAX_PAGE_SIZE: i64 = 100;
+// Most messages a stream replays when resuming, clients further behind page through the
+// list endpoint instead
+const MAX_REPLAY_SIZE: i64 = 10_000;
+// Comment sent on idle streams so proxies don't close them
+const STREAM_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A page of a list endpoint.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // Pass as `cursor` to get the next page, null on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    // Messages are sorted by creation_timestamp, newest first by default
+    pub order: Option<SortOrder>,
+    // Only messages created by this address
+    pub creator: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    // Replay the messages updated from this version on, overridden by Last-Event-ID
+    pub since_version: Option<i64>,
+}
+
+/// Error returned by the read API, rendered as a JSON body with the matching status.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn unavailable(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn as_response(&self) -> Response {
+        Json(serde_json::json!({ "error": self.message }))
+            .with_status(self.status)
+            .into_response()
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        tracing::warn!("Error running read API query: {:?}", e);
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "database query failed".to_string(),
+        }
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+fn db_pool() -> Result<ArcDbPool, ApiError> {
+    PIPELINE_STATE
+        .db_pool()
+        .ok_or_else(|| ApiError::unavailable("processor has not started yet"))
+}
+
+async fn get_conn(pool: &ArcDbPool) -> Result<DbPoolConnection<'_>, ApiError> {
+    pool.get()
+        .await
+        .map_err(|e| ApiError::unavailable(format!("failed to get a connection: {}", e)))
+}
+
+fn page_size(limit: Option<i64>) -> Result<i64, ApiError> {
+    match limit.unwrap_or(DEFAULT_PAGE_SIZE) {
+        limit @ 1..=MAX_PAGE_SIZE => Ok(limit),
+        limit => Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}, got {}",
+            MAX_PAGE_SIZE, limit
+        ))),
+    }
+}
+
+/// Cursors are the sort key of the last item of a page followed by the address that breaks
+/// ties, e.g. `1727740800:0x1`.
+fn encode_cursor(key: i64, addr: &str) -> String {
+    format!("{}:{}", key, addr)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(i64, String), ApiError> {
+    cursor
+        .split_once(':')
+        .and_then(|(key, addr)| Some((key.parse().ok()?, addr.to_string())))
+        .ok_or_else(|| ApiError::bad_request(format!("invalid cursor {:?}", cursor)))
+}
+
+/// Loads one more row than the page size to tell whether there is a next page.
+fn into_page<T>(mut items: Vec<T>, limit: i64, cursor: impl Fn(&T) -> String) -> Page<T> {
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(cursor)
+    } else {
+        None
+    };
+    Page { items, next_cursor }
+}
+
+#[handler]
+pub async fn list_messages(Query(params): Query<MessagesQuery>) -> ApiResult<Page<Message>> {
+    let limit = page_size(params.limit)?;
+    let pool = db_pool()?;
+    let conn = &mut get_conn(&pool).await?;
+
+    let mut query = messages::table.into_boxed();
+    if let Some(creator) = &params.creator {
+        query = query.filter(messages::creator_addr.eq(standardize_address(creator)));
+    }
+    let ascending = matches!(params.order, Some(SortOrder::Asc));
+    if let Some(cursor) = &params.cursor {
+        let (creation_timestamp, message_obj_addr) = decode_cursor(cursor)?;
+        query = if ascending {
+            query.filter(
+                messages::creation_timestamp.gt(creation_timestamp).or(
+                    messages::creation_timestamp
+                        .eq(creation_timestamp)
+                        .and(messages::message_obj_addr.gt(message_obj_addr)),
+                ),
+            )
+        } else {
+            query.filter(
+                messages::creation_timestamp.lt(creation_timestamp).or(
+                    messages::creation_timestamp
+                        .eq(creation_timestamp)
+                        .and(messages::message_obj_addr.lt(message_obj_addr)),
+                ),
+            )
+        };
+    }
+    query = if ascending {
+        query.order((
+            messages::creation_timestamp.asc(),
+            messages::message_obj_addr.asc(),
+        ))
+    } else {
+        query.order((
+            messages::creation_timestamp.desc(),
+            messages::message_obj_addr.desc(),
+        ))
+    };
+
+    let items = query.limit(limit + 1).load::<Message>(conn).await?;
+    Ok(Json(into_page(items, limit, |message| {
+        encode_cursor(message.creation_timestamp, &message.message_obj_addr)
+    })))
+}
+
+#[handler]
+pub async fn get_message(Path(obj_addr): Path<String>) -> ApiResult<Message> {
+    let pool = db_pool()?;
+    let conn = &mut get_conn(&pool).await?;
+    messages::table
+        .find(standardize_address(&obj_addr))
+        .first::<Message>(conn)
+        .await
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("message {} not found", obj_addr)))
+}
+
+#[handler]
+pub async fn get_user_stats(Path(addr): Path<String>) -> ApiResult<UserStat> {
+    let pool = db_pool()?;
+    let conn = &mut get_conn(&pool).await?;
+    user_stats::table
+        .find(standardize_address(&addr))
+        .first::<UserStat>(conn)
+        .await
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("no stats for user {}", addr)))
+}
+
+#[handler]
+pub async fn list_leaderboard(
+    Query(params): Query<LeaderboardQuery>,
+) -> ApiResult<Page<LeaderboardEntry>> {
+    let limit = page_size(params.limit)?;
+    let pool = db_pool()?;
+    let conn = &mut get_conn(&pool).await?;
+
+    let mut query = leaderboard::table
+        .order((leaderboard::rank.asc(), leaderboard::user_addr.asc()))
+        .into_boxed();
+    if let Some(cursor) = &params.cursor {
+        let (rank, user_addr) = decode_cursor(cursor)?;
+        query = query.filter(
+            leaderboard::rank.gt(rank).or(leaderboard::rank
+                .eq(rank)
+                .and(leaderboard::user_addr.gt(user_addr))),
+        );
+    }
+
+    let items = query
+        .limit(limit + 1)
+        .load::<LeaderboardEntry>(conn)
+        .await?;
+    Ok(Json(into_page(items, limit, |entry| {
+        encode_cursor(entry.rank, &entry.user_addr)
+    })))
+}
+
+#[handler]
+pub async fn get_status() -> ApiResult<ProcessorStatusQuery> {
+    let pool = db_pool()?;
+    let processor_name = PIPELINE_STATE
+        .processor_name()
+        .ok_or_else(|| ApiError::unavailable("processor has not started yet"))?;
+    let conn = &mut get_conn(&pool).await?;
+    processor_status::table
+        .filter(processor_status::processor.eq(&processor_name))
+        .first::<ProcessorStatusQuery>(conn)
+        .await
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("no processor status recorded yet"))
+}
+
+/// Version to resume a stream from, the Last-Event-ID an `EventSource` sends when it
+/// reconnects, or the `since_version` parameter.
+fn resume_version(headers: &HeaderMap, params: &StreamQuery) -> Result<Option<i64>, ApiError> {
+    match headers.get("Last-Event-ID") {
+        Some(last_event_id) => last_event_id
+            .to_str()
+            .ok()
+            .and_then(|id| id.parse().ok())
+            .map(Some)
+            .ok_or_else(|| ApiError::bad_request("invalid Last-Event-ID")),
+        None => Ok(params.since_version),
+    }
+}
+
+fn message_event(message: &Message) -> Event {
+    Event::message(serde_json::to_string(message).unwrap_or_default())
+        .event_type("message")
+        .id(message.last_update_txn_version.to_string())
+}
+
+fn user_stat_event(user_stat: &UserStat) -> Event {
+    Event::message(serde_json::to_string(user_stat).unwrap_or_default()).event_type("user_stat")
+}
+
+/// Messages updated from `since_version` on, in version order, optionally only a creator's.
+async fn load_replay(
+    conn: &mut DbPoolConnection<'_>,
+    since_version: i64,
+    creator: Option<&str>,
+) -> Result<Vec<Message>, ApiError> {
+    let mut query = messages::table
+        .filter(messages::last_update_txn_version.ge(since_version))
+        .order((
+            messages::last_update_txn_version.asc(),
+            messages::message_obj_addr.asc(),
+        ))
+        .into_boxed();
+    if let Some(creator) = creator {
+        query = query.filter(messages::creator_addr.eq(creator.to_string()));
+    }
+    let messages = query
+        .limit(MAX_REPLAY_SIZE + 1)
+        .load::<Message>(conn)
+        .await?;
+    if messages.len() as i64 > MAX_REPLAY_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "more than {} messages to replay, page through /messages instead",
+            MAX_REPLAY_SIZE
+        )));
+    }
+    Ok(messages)
+}
+
+/// Updates published after the subscription, optionally only a user's messages and stats.
+/// Messages already replayed are skipped. The stream ends when the client falls too far
+/// behind, an `EventSource` then reconnects and resumes from the last message it received.
+fn live_events(
+    receiver: Receiver<LiveUpdate>,
+    user_addr: Option<String>,
+    replayed: AHashSet<(String, i64)>,
+) -> impl Stream<Item = Event> {
+    stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(update) => Some((update, receiver)),
+            Err(RecvError::Lagged(_) | RecvError::Closed) => None,
+        }
+    })
+    .filter_map(move |update| {
+        let event = match &update {
+            LiveUpdate::Message(message)
+                if user_addr
+                    .as_ref()
+                    .is_none_or(|user_addr| *user_addr == message.creator_addr)
+                    && !replayed.contains(&(
+                        message.message_obj_addr.clone(),
+                        message.last_update_txn_version,
+                    )) =>
+            {
+                Some(message_event(message))
+            }
+            LiveUpdate::UserStat(user_stat)
+                if user_addr
+                    .as_ref()
+                    .is_some_and(|user_addr| *user_addr == user_stat.user_addr) =>
+            {
+                Some(user_stat_event(user_stat))
+            }
+            _ => None,
+        };
+        futures_util::future::ready(event)
+    })
+}
+
+/// Replays what was missed since the resume version, if any, then streams live updates. The
+/// subscription starts before the replay is loaded so nothing committed in between is lost.
+async fn stream_events(
+    resume_version: Option<i64>,
+    user_addr: Option<String>,
+) -> Result<SSE, ApiError> {
+    let receiver = LIVE_UPDATES.subscribe();
+    let mut events = vec![];
+    let mut replayed = AHashSet::new();
+    if resume_version.is_some() || user_addr.is_some() {
+        let pool = db_pool()?;
+        let conn = &mut get_conn(&pool).await?;
+        // A user's stream starts with their current stats
+        if let Some(user_addr) = &user_addr {
+            if let Some(user_stat) = user_stats::table
+                .find(user_addr)
+                .first::<UserStat>(conn)
+                .await
+                .optional()?
+            {
+                events.push(user_stat_event(&user_stat));
+            }
+        }
+        if let Some(since_version) = resume_version {
+            for message in load_replay(conn, since_version, user_addr.as_deref()).await? {
+                events.push(message_event(&message));
+                replayed.insert((message.message_obj_addr, message.last_update_txn_version));
+            }
+        }
+    }
+    Ok(
+        SSE::new(stream::iter(events).chain(live_events(receiver, user_addr, replayed)))
+            .keep_alive(STREAM_KEEP_ALIVE),
+    )
+}
+
+#[handler]
+pub async fn stream_messages(
+    headers: &HeaderMap,
+    Query(params): Query<StreamQuery>,
+) -> Result<SSE, ApiError> {
+    stream_events(resume_version(headers, &params)?, None).await
+}
+
+#[handler]
+pub async fn stream_user(
+    headers: &HeaderMap,
+    Path(addr): Path<String>,
+    Query(params): Query<StreamQuery>,
+) -> Result<SSE, ApiError> {
+    stream_events(
+        resume_version(headers, &params)?,
+        Some(standardize_address(&addr)),
+    )
+    .await
+}
@@ -0,0 +1,149 @@
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::{
+    extractor::ContractEvent,
+    storers::{
+        create_message_event_storer::process_create_message_events,
+        update_message_event_storer::process_update_message_events,
+    },
+};
+use crate::{
+    config::{points_config::PointsConfig, webhook_config::WebhookConfig},
+    db_models::{
+        event_ledger::EventLedgerEntry,
+        message::{CreateMessageEventOnChain, UpdateMessageEventOnChain},
+    },
+    utils::database_utils::ArcDbPool,
+};
+
+/// Handlers that an event type can be mapped to in `contract_config.events`.
+///
+/// To index a new event, add a variant here, decode the event into a `ContractEvent` in
+/// `decode`, persist the decoded events in `store`, then list the event type in the config.
+/// The events of a batch are stored handler by handler in the order the variants are declared,
+/// whatever the order of the config, so messages are always created before they are updated.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    strum::Display,
+    strum::EnumString,
+    strum::IntoStaticStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EventHandler {
+    CreateMessage,
+    UpdateMessage,
+}
+
+impl EventHandler {
+    /// Decodes the JSON data of a single event emitted under `contract_addr`, along with
+    /// the ledger entry that keeps its effect on user_stats from being applied twice.
+    pub fn decode(
+        &self,
+        contract_addr: &str,
+        txn_version: i64,
+        event_idx: i64,
+        data: &str,
+    ) -> Result<ContractEvent> {
+        match self {
+            EventHandler::CreateMessage => {
+                let create_message_event_on_chain: CreateMessageEventOnChain =
+                    serde_json::from_str(data).context("Failed to parse CreateMessageEvent")?;
+                let message = create_message_event_on_chain.to_db_message(
+                    contract_addr,
+                    txn_version,
+                    event_idx,
+                )?;
+                let ledger_entry = EventLedgerEntry::new(
+                    txn_version,
+                    event_idx,
+                    *self,
+                    &message.creator_addr,
+                    message.creation_timestamp,
+                );
+                Ok(ContractEvent::CreateMessageEvent(message, ledger_entry))
+            }
+            EventHandler::UpdateMessage => {
+                let update_message_event_on_chain: UpdateMessageEventOnChain =
+                    serde_json::from_str(data).context("Failed to parse UpdateMessageEvent")?;
+                let message = update_message_event_on_chain.to_db_message(
+                    contract_addr,
+                    txn_version,
+                    event_idx,
+                )?;
+                let ledger_entry = EventLedgerEntry::new(
+                    txn_version,
+                    event_idx,
+                    *self,
+                    &message.creator_addr,
+                    message.last_update_timestamp,
+                );
+                Ok(ContractEvent::UpdateMessageEvent(message, ledger_entry))
+            }
+        }
+    }
+
+    /// Stores all events of a batch that were decoded by this handler.
+    pub async fn store(
+        &self,
+        pool: ArcDbPool,
+        per_table_chunk_sizes: AHashMap<String, usize>,
+        points_config: Arc<PointsConfig>,
+        webhook_config: Option<Arc<WebhookConfig>>,
+        events: Vec<ContractEvent>,
+    ) -> Result<(), ProcessorError> {
+        match self {
+            EventHandler::CreateMessage => {
+                let create_events = events
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        ContractEvent::CreateMessageEvent(message, ledger_entry) => {
+                            Some((message, ledger_entry))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                process_create_message_events(
+                    pool,
+                    per_table_chunk_sizes,
+                    points_config,
+                    webhook_config,
+                    create_events,
+                )
+                .await
+            }
+            EventHandler::UpdateMessage => {
+                let update_events = events
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        ContractEvent::UpdateMessageEvent(message, ledger_entry) => {
+                            Some((message, ledger_entry))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                process_update_message_events(
+                    pool,
+                    per_table_chunk_sizes,
+                    points_config,
+                    webhook_config,
+                    update_events,
+                )
+                .await
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use ahash::AHashMap;
-use anyhow::Result;
+use ahash::{AHashMap, AHashSet};
+use anyhow::{Context, Result};
 use aptos_indexer_processor_sdk::{
     aptos_protos::transaction::v1::{
         transaction::TxnData, write_set_change::Change, Event as EventPB, MoveModuleBytecode,
@@ -12,10 +12,22 @@ use aptos_indexer_processor_sdk::{
 use async_trait::async_trait;
 use rayon::prelude::*;
 
-use crate::db_models::{
-    message::{CreateMessageEventOnChain, Message, UpdateMessageEventOnChain},
-    module_upgrade::ModuleUpgrade,
-    package_upgrade::{PackageUpgrade, PackageUpgradeChangeOnChain},
+use super::event_handler::EventHandler;
+use crate::{
+    config::{
+        indexer_processor_config::{ContractAddressConfig, ContractConfig},
+        processor_config::DecodeErrorPolicy,
+    },
+    db_models::{
+        event_ledger::EventLedgerEntry,
+        failed_item::{FailedItem, FailedItemQuery, FailedItemType},
+        generic_event::GenericEvent,
+        message::Message,
+        message_revision::MessageRevision,
+        module_upgrade::ModuleUpgrade,
+        package_upgrade::{PackageUpgrade, PackageUpgradeChangeOnChain},
+    },
+    utils::{counters::EVENTS_PROCESSED_COUNT, pipeline_state::PIPELINE_STATE},
 };
 
 /// Extractor is a step that extracts events and their metadata from transactions.
@@ -23,12 +35,113 @@ pub struct Extractor
 where
     Self: Sized + Send + 'static,
 {
-    contract_address: String,
+    // Configured addresses, standardized
+    contracts: Vec<ContractAddressConfig>,
+    // Key is the fully-qualified event type (address::module::Struct),
+    // value is the configured address it's emitted under and the handler that decodes it
+    event_handlers: AHashMap<String, (String, EventHandler)>,
+    capture_all_events: bool,
+    on_decode_error: DecodeErrorPolicy,
+    // Latest upgrade_number of each package, keyed by (address, package name), as of the
+    // package registries extracted so far
+    upgrade_numbers: AHashMap<(String, String), i64>,
 }
 
 impl Extractor {
-    pub fn new(contract_address: String) -> Self {
-        Self { contract_address }
+    pub fn new(contract_config: &ContractConfig, on_decode_error: DecodeErrorPolicy) -> Self {
+        let contracts = contract_config
+            .addresses
+            .iter()
+            .map(|contract| ContractAddressConfig {
+                address: standardize_address(&contract.address),
+                modules: contract.modules.clone(),
+            })
+            .collect::<Vec<_>>();
+        let event_handlers = contracts
+            .iter()
+            .flat_map(|contract| {
+                contract_config
+                    .events
+                    .iter()
+                    .filter(|event| {
+                        let module_name = event.event_type.split("::").next().unwrap_or_default();
+                        contract.includes_module(module_name)
+                    })
+                    .map(|event| {
+                        (
+                            format!("{}::{}", contract.address, event.event_type),
+                            (contract.address.clone(), event.handler),
+                        )
+                    })
+            })
+            .collect();
+        Self {
+            contracts,
+            event_handlers,
+            capture_all_events: contract_config.capture_all_events,
+            on_decode_error,
+            upgrade_numbers: AHashMap::new(),
+        }
+    }
+
+    /// Starts from the upgrade numbers already stored, so a registry that lists a package
+    /// without a new upgrade_number isn't recorded as an upgrade after a restart.
+    pub fn with_upgrade_numbers(
+        mut self,
+        upgrade_numbers: AHashMap<(String, String), i64>,
+    ) -> Self {
+        self.upgrade_numbers = upgrade_numbers;
+        self
+    }
+
+    /// Decodes a dead-lettered item again, with the current code and config.
+    pub fn redecode(&self, failed_item: &FailedItemQuery) -> Result<TransactionContextData> {
+        let item_type: FailedItemType = failed_item
+            .item_type
+            .parse()
+            .with_context(|| format!("Unknown item type {}", failed_item.item_type))?;
+        let mut data = TransactionContextData::default();
+        match item_type {
+            FailedItemType::Event => {
+                let (contract_addr, handler) =
+                    standardize_event_type(failed_item.type_str.as_str())
+                        .and_then(|event_type| self.event_handlers.get(&event_type))
+                        .with_context(|| {
+                            format!("No handler configured for {}", failed_item.type_str)
+                        })?;
+                // Same context as when the event was dead-lettered
+                data.events.push(
+                    handler
+                        .decode(
+                            contract_addr,
+                            failed_item.txn_version,
+                            failed_item.item_index,
+                            failed_item.raw_data.as_str(),
+                        )
+                        .with_context(|| format!("Failed to decode with {} handler", handler))?,
+                );
+            }
+            FailedItemType::Change => {
+                let changes: Vec<WriteSetChange> =
+                    serde_json::from_str(failed_item.raw_data.as_str())
+                        .context("Failed to parse dead-lettered write set changes")?;
+                let upgrades = ContractUpgradeChange::from_changes(
+                    &self.contracts,
+                    &self.upgrade_numbers,
+                    failed_item.txn_version,
+                    changes.as_slice(),
+                );
+                if let Some(failed_item) = upgrades
+                    .failed_items
+                    .first()
+                    .or(upgrades.inconsistent_items.first())
+                {
+                    anyhow::bail!("{}", failed_item.error);
+                }
+                data.changes = upgrades.changes;
+            }
+        }
+        Ok(data)
     }
 }
 
@@ -50,7 +163,9 @@ impl Processable for Extractor {
         &mut self,
         item: TransactionContext<Vec<Transaction>>,
     ) -> Result<Option<TransactionContext<TransactionContextData>>, ProcessorError> {
-        let results: Vec<(Vec<ContractEvent>, Vec<ContractUpgradeChange>)> = item
+        PIPELINE_STATE.record_batch_received();
+        // Inconsistent upgrades are kept apart, they're dead-lettered whatever the policy
+        let results: Vec<(TransactionContextData, Vec<FailedItem>)> = item
             .data
             .par_iter()
             .map(|txn| {
@@ -60,7 +175,7 @@ impl Processable for Extractor {
                         if info.success {
                             info
                         } else {
-                            return (vec![], vec![]);
+                            return Default::default();
                         }
                     }
                     None => {
@@ -68,7 +183,7 @@ impl Processable for Extractor {
                             transaction_version = txn_version,
                             "Transaction info doesn't exist"
                         );
-                        return (vec![], vec![]);
+                        return Default::default();
                     }
                 };
                 let txn_data = match txn.txn_data.as_ref() {
@@ -78,7 +193,7 @@ impl Processable for Extractor {
                             transaction_version = txn_version,
                             "Transaction data doesn't exist"
                         );
-                        return (vec![], vec![]);
+                        return Default::default();
                     }
                 };
                 let raw_events = match txn_data {
@@ -88,105 +203,287 @@ impl Processable for Extractor {
                     _ => &vec![],
                 };
 
-                let txn_events =
-                    ContractEvent::from_events(self.contract_address.as_str(), raw_events);
+                let (events, mut failed_items) =
+                    ContractEvent::from_events(&self.event_handlers, txn_version, raw_events);
 
-                let txn_changes = ContractUpgradeChange::from_changes(
-                    self.contract_address.as_str(),
+                let upgrades = ContractUpgradeChange::from_changes(
+                    &self.contracts,
+                    &self.upgrade_numbers,
                     txn_version,
                     txn_info.changes.as_slice(),
                 );
+                failed_items.extend(upgrades.failed_items);
+
+                let generic_events = if self.capture_all_events {
+                    GenericEvent::from_events(&self.contracts, txn_version, raw_events)
+                } else {
+                    vec![]
+                };
 
-                (txn_events, txn_changes)
+                (
+                    TransactionContextData {
+                        events,
+                        changes: upgrades.changes,
+                        generic_events,
+                        failed_items,
+                    },
+                    upgrades.inconsistent_items,
+                )
             })
-            .collect::<Vec<(Vec<ContractEvent>, Vec<ContractUpgradeChange>)>>();
-
-        let (events, changes): (Vec<ContractEvent>, Vec<ContractUpgradeChange>) =
-            results.into_iter().fold(
-                (Vec::new(), Vec::new()),
-                |(mut events_acc, mut changes_acc), (events, changes)| {
-                    events_acc.extend(events);
-                    changes_acc.extend(changes);
-                    (events_acc, changes_acc)
-                },
+            .collect::<Vec<_>>();
+
+        let (mut data, inconsistent_items) = results.into_iter().fold(
+            (TransactionContextData::default(), vec![]),
+            |(mut data_acc, mut inconsistent_acc), (txn_data, inconsistent_items)| {
+                data_acc.events.extend(txn_data.events);
+                data_acc.changes.extend(txn_data.changes);
+                data_acc.generic_events.extend(txn_data.generic_events);
+                data_acc.failed_items.extend(txn_data.failed_items);
+                inconsistent_acc.extend(inconsistent_items);
+                (data_acc, inconsistent_acc)
+            },
+        );
+
+        // Transactions are extracted in parallel, so upgrades in the same batch are compared with
+        // the upgrade numbers from before the batch. A repeated upgrade_number is already
+        // stored and isn't written again
+        for change in &data.changes {
+            if let ContractUpgradeChange::PackageUpgradeChange(package) = change {
+                let upgrade_number = self
+                    .upgrade_numbers
+                    .entry((package.package_addr.clone(), package.package_name.clone()))
+                    .or_insert(package.upgrade_number);
+                *upgrade_number = (*upgrade_number).max(package.upgrade_number);
+            }
+        }
+
+        for failed_item in &data.failed_items {
+            tracing::warn!(
+                transaction_version = failed_item.txn_version,
+                item_type = %failed_item.item_type,
+                item_index = failed_item.item_index,
+                type_str = %failed_item.type_str,
+                on_decode_error = ?self.on_decode_error,
+                "Failed to decode item: {}",
+                failed_item.error
+            );
+        }
+        for failed_item in &inconsistent_items {
+            tracing::warn!(
+                transaction_version = failed_item.txn_version,
+                item_index = failed_item.item_index,
+                type_str = %failed_item.type_str,
+                "Dead-lettering inconsistent upgrade: {}",
+                failed_item.error
             );
+        }
+        match self.on_decode_error {
+            DecodeErrorPolicy::Halt => {
+                if let Some(failed_item) = data.failed_items.first() {
+                    return Err(ProcessorError::ProcessError {
+                        message: format!(
+                            "Failed to decode {} {} of transaction {} ({}): {}",
+                            failed_item.item_type,
+                            failed_item.item_index,
+                            failed_item.txn_version,
+                            failed_item.type_str,
+                            failed_item.error
+                        ),
+                    });
+                }
+            }
+            DecodeErrorPolicy::Skip => data.failed_items.clear(),
+            DecodeErrorPolicy::DeadLetter => {}
+        }
+        data.failed_items.extend(inconsistent_items);
 
         Ok(Some(TransactionContext {
-            data: TransactionContextData { events, changes },
+            data,
             metadata: item.metadata,
         }))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TransactionContextData {
     pub events: Vec<ContractEvent>,
     pub changes: Vec<ContractUpgradeChange>,
+    pub generic_events: Vec<GenericEvent>,
+    pub failed_items: Vec<FailedItem>,
+}
+
+impl TransactionContextData {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+            && self.changes.is_empty()
+            && self.generic_events.is_empty()
+            && self.failed_items.is_empty()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ContractEvent {
-    CreateMessageEvent(Message),
-    UpdateMessageEvent(Message),
+    CreateMessageEvent(Message, EventLedgerEntry),
+    UpdateMessageEvent(Message, EventLedgerEntry),
 }
 
 impl ContractEvent {
-    fn from_event(contract_address: &str, event_idx: usize, event: &EventPB) -> Option<Self> {
-        // use standardize_address to pad the address in event type before processing
-        let parts = event.type_str.split("::").collect::<Vec<_>>();
-        let t = standardize_address(parts[0]) + "::" + parts[1] + "::" + parts[2];
-        let should_include = t.starts_with(contract_address);
-
-        if should_include {
-            if t.starts_with(
-                format!(
-                    "{}::custom_indexer_ex_message_board::CreateMessageEvent",
-                    contract_address
-                )
-                .as_str(),
-            ) {
-                println!("CreateMessageEvent {}", event.data.as_str());
-                let create_message_event_on_chain: CreateMessageEventOnChain =
-                    serde_json::from_str(event.data.as_str()).unwrap_or_else(|_| {
-                        panic!(
-                            "Failed to parse CreateMessageEvent, {}",
-                            event.data.as_str()
-                        )
-                    });
-                Some(ContractEvent::CreateMessageEvent(
-                    create_message_event_on_chain.to_db_message(),
-                ))
-            } else if t.starts_with(
-                format!(
-                    "{}::custom_indexer_ex_message_board::UpdateMessageEvent",
-                    contract_address
+    fn from_event(
+        event_handlers: &AHashMap<String, (String, EventHandler)>,
+        txn_version: i64,
+        event_idx: usize,
+        event: &EventPB,
+    ) -> Option<Result<Self, FailedItem>> {
+        // use standardize_event_type to pad the address in event type before looking up its handler
+        let event_type = standardize_event_type(event.type_str.as_str())?;
+        let (contract_addr, handler) = event_handlers.get(&event_type)?;
+        EVENTS_PROCESSED_COUNT
+            .with_label_values(&[event_type.as_str()])
+            .inc();
+        Some(
+            handler
+                .decode(
+                    contract_addr,
+                    txn_version,
+                    event_idx as i64,
+                    event.data.as_str(),
                 )
-                .as_str(),
-            ) {
-                println!("UpdateMessageEvent {}", event.data.as_str());
-                let update_message_event_on_chain: UpdateMessageEventOnChain =
-                    serde_json::from_str(event.data.as_str()).unwrap_or_else(|_| {
-                        panic!(
-                            "Failed to parse UpdateMessageEvent, {}",
-                            event.data.as_str()
-                        )
-                    });
-                Some(ContractEvent::UpdateMessageEvent(
-                    update_message_event_on_chain.to_db_message(event_idx as i64),
-                ))
-            } else {
-                None
+                .map_err(|e| {
+                    FailedItem::new(
+                        txn_version,
+                        FailedItemType::Event,
+                        event_idx as i64,
+                        event.type_str.as_str(),
+                        event.data.clone(),
+                        &e.context(format!("Failed to decode with {} handler", handler)),
+                    )
+                }),
+        )
+    }
+
+    /// Decodes the events that have a configured handler, returning the ones that failed to
+    /// decode separately.
+    pub fn from_events(
+        event_handlers: &AHashMap<String, (String, EventHandler)>,
+        txn_version: i64,
+        events: &[EventPB],
+    ) -> (Vec<Self>, Vec<FailedItem>) {
+        let mut contract_events = vec![];
+        let mut failed_items = vec![];
+        events
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, event)| Self::from_event(event_handlers, txn_version, idx, event))
+            .for_each(|result| match result {
+                Ok(contract_event) => contract_events.push(contract_event),
+                Err(failed_item) => failed_items.push(failed_item),
+            });
+        (contract_events, failed_items)
+    }
+
+    /// Handler that decoded this event and is responsible for storing it.
+    pub fn handler(&self) -> EventHandler {
+        match self {
+            ContractEvent::CreateMessageEvent(..) => EventHandler::CreateMessage,
+            ContractEvent::UpdateMessageEvent(..) => EventHandler::UpdateMessage,
+        }
+    }
+
+    /// Revision of the message this event created or updated.
+    pub fn message_revision(&self) -> Option<MessageRevision> {
+        match self {
+            ContractEvent::CreateMessageEvent(message, _)
+            | ContractEvent::UpdateMessageEvent(message, _) => {
+                Some(MessageRevision::from_message(message))
             }
-        } else {
-            None
         }
     }
+}
+
+/// Pads the address of an event type and drops its type arguments, e.g.
+/// `0x1::coin::Deposit<0x1::aptos_coin::AptosCoin>` becomes `0x0...01::coin::Deposit`.
+/// Returns None for event types that aren't Move structs.
+fn standardize_event_type(type_str: &str) -> Option<String> {
+    let (address, module, name, _) = parse_event_type(type_str)?;
+    Some(format!("{}::{}::{}", address, module, name))
+}
+
+/// Splits an event type into its standardized address, module, struct name and type
+/// arguments. Returns None for event types that aren't Move structs.
+pub fn parse_event_type(type_str: &str) -> Option<(String, String, String, Vec<String>)> {
+    let (base_type, type_args) = match type_str.split_once('<') {
+        Some((base_type, type_args)) => (base_type, split_type_args(type_args.strip_suffix('>')?)),
+        None => (type_str, vec![]),
+    };
+    let mut parts = base_type.splitn(3, "::");
+    let (address, module, name) = (parts.next()?, parts.next()?, parts.next()?);
+    Some((
+        standardize_address(address),
+        module.to_string(),
+        name.to_string(),
+        type_args,
+    ))
+}
+
+/// Splits type arguments at the commas that aren't nested in another type's arguments.
+fn split_type_args(type_args: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in type_args.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(type_args[start..idx].trim().to_string());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(type_args[start..].trim().to_string());
+    args
+}
 
-    pub fn from_events(contract_address: &str, events: &[EventPB]) -> Vec<Self> {
+impl GenericEvent {
+    /// Events emitted by the configured modules, undecoded. Data that isn't valid JSON is kept
+    /// as a string with an ABI error, so the event is still recorded.
+    pub fn from_events(
+        contracts: &[ContractAddressConfig],
+        txn_version: i64,
+        events: &[EventPB],
+    ) -> Vec<Self> {
         events
             .iter()
             .enumerate()
-            .filter_map(|(idx, event)| Self::from_event(contract_address, idx, event))
+            .filter_map(|(idx, event)| {
+                let (account_address, module_name, struct_name, type_args) =
+                    parse_event_type(event.type_str.as_str())?;
+                if !contracts.iter().any(|contract| {
+                    contract.address == account_address
+                        && contract.includes_module(module_name.as_str())
+                }) {
+                    return None;
+                }
+                let (data, abi_error) = match serde_json::from_str(event.data.as_str()) {
+                    Ok(data) => (data, None),
+                    Err(e) => (
+                        serde_json::Value::String(event.data.clone()),
+                        Some(format!("Event data is not valid JSON: {}", e)),
+                    ),
+                };
+                Some(GenericEvent {
+                    txn_version,
+                    event_index: idx as i64,
+                    account_address,
+                    module_name,
+                    struct_name,
+                    type_args,
+                    data,
+                    upgrade_number: None,
+                    abi_error,
+                })
+            })
             .collect()
     }
 }
@@ -197,110 +494,359 @@ pub enum ContractUpgradeChange {
     PackageUpgradeChange(PackageUpgrade),
 }
 
+/// The index of an upgrade change along with what doesn't match in it.
+type UpgradeInconsistency = (usize, String);
+
+/// The upgrades decoded from a transaction's changes.
+#[derive(Debug, Default)]
+pub struct ExtractedUpgrades {
+    pub changes: Vec<ContractUpgradeChange>,
+    /// Upgrades that couldn't be decoded, handled according to the decode error policy
+    pub failed_items: Vec<FailedItem>,
+    /// Upgrades whose package registry doesn't match the modules written by the transaction,
+    /// always dead-lettered
+    pub inconsistent_items: Vec<FailedItem>,
+}
+
 impl ContractUpgradeChange {
+    /// Decodes the package and module upgrades of a transaction under each configured address.
+    /// The package registry lists every package at the address, only the packages whose
+    /// upgrade_number is greater than the one in `upgrade_numbers` are recorded as upgraded.
+    /// A package that isn't in `upgrade_numbers`, e.g. when indexing starts after it was
+    /// published, is upgraded if the transaction wrote one of its modules.
+    ///
+    /// If any part of the upgrade at an address can't be decoded, its upgrade related changes
+    /// are returned as a single failed item. A package listing a module that wasn't written
+    /// alongside the others, or a written module that no package lists, is an inconsistency:
+    /// the affected packages are skipped and the changes are returned as an inconsistent item.
     pub fn from_changes(
-        contract_address: &str,
+        contracts: &[ContractAddressConfig],
+        upgrade_numbers: &AHashMap<(String, String), i64>,
         txn_version: i64,
         changes: &[WriteSetChange],
-    ) -> Vec<Self> {
-        let mut raw_module_changes: AHashMap<(String, String), MoveModuleBytecode> =
-            AHashMap::new();
-        let mut raw_package_changes: Vec<PackageUpgradeChangeOnChain> = vec![];
+    ) -> ExtractedUpgrades {
+        let mut extracted = ExtractedUpgrades::default();
+        for contract in contracts {
+            let upgrade_changes = changes
+                .iter()
+                .enumerate()
+                .filter(|(_, change)| is_upgrade_change(contract.address.as_str(), change))
+                .collect::<Vec<(usize, &WriteSetChange)>>();
+            if upgrade_changes.is_empty() {
+                continue;
+            }
+            let to_failed_item = |change_idx: usize, error: &anyhow::Error| {
+                let raw_data = serde_json::to_string(
+                    &upgrade_changes
+                        .iter()
+                        .map(|(_, change)| *change)
+                        .collect::<Vec<&WriteSetChange>>(),
+                )
+                .unwrap_or_default();
+                FailedItem::new(
+                    txn_version,
+                    FailedItemType::Change,
+                    change_idx as i64,
+                    change_type_str(&changes[change_idx]).as_str(),
+                    raw_data,
+                    error,
+                )
+            };
 
-        changes
-            .iter()
-            .for_each(|change| match change.change.as_ref() {
-                Some(change) => match change {
-                    Change::WriteModule(write_module_change) => {
-                        if standardize_address(write_module_change.address.as_str())
-                            == contract_address
-                        {
-                            raw_module_changes.insert(
-                                (
-                                    standardize_address(write_module_change.address.as_str()),
-                                    write_module_change
-                                        .data
-                                        .clone()
-                                        .unwrap_or_else(|| {
-                                            panic!("MoveModuleBytecode data is missing",)
-                                        })
-                                        .abi
-                                        .clone()
-                                        .unwrap_or_else(|| {
-                                            panic!("MoveModuleBytecode abi is missing",)
-                                        })
-                                        .name,
-                                ),
-                                write_module_change.data.clone().unwrap(),
-                            );
-                        }
-                    }
-                    Change::WriteResource(write_resource_change) => {
-                        if standardize_address(write_resource_change.address.as_str())
-                            == contract_address
-                            && write_resource_change.type_str == "0x1::code::PackageRegistry"
-                        {
-                            let package_upgrade: PackageUpgradeChangeOnChain =
-                                serde_json::from_str(write_resource_change.data.as_str())
-                                    .unwrap_or_else(|_| {
-                                        panic!(
-                                            "Failed to parse PackageUpgradeChangeOnChain, {}",
-                                            write_resource_change.data.as_str()
-                                        )
-                                    });
-                            raw_package_changes.push(package_upgrade);
-                        }
+            match Self::try_from_changes(contract, upgrade_numbers, txn_version, &upgrade_changes) {
+                Ok((upgrades, mut inconsistencies)) => {
+                    extracted.changes.extend(upgrades);
+                    if !inconsistencies.is_empty() {
+                        inconsistencies.sort_by_key(|(change_idx, _)| *change_idx);
+                        let error = anyhow::anyhow!(
+                            "Inconsistent upgrade: {}",
+                            inconsistencies
+                                .iter()
+                                .map(|(_, error)| error.as_str())
+                                .collect::<Vec<_>>()
+                                .join("; ")
+                        );
+                        extracted
+                            .inconsistent_items
+                            .push(to_failed_item(inconsistencies[0].0, &error));
                     }
-                    _ => {}
-                },
-                None => {}
-            });
+                }
+                Err((change_idx, error)) => {
+                    extracted
+                        .failed_items
+                        .push(to_failed_item(change_idx, &error));
+                }
+            }
+        }
+        extracted
+    }
 
-        let package_changes = raw_package_changes
-            .iter()
-            .flat_map(|package_change| {
-                package_change.to_db_package_upgrade(txn_version, contract_address.to_string())
-            })
-            .collect::<Vec<PackageUpgrade>>();
+    /// Returns the upgrades along with the inconsistencies found, each with the index of the
+    /// offending change. Returns the index of the offending change along with the error if the
+    /// upgrade can't be decoded.
+    fn try_from_changes(
+        contract: &ContractAddressConfig,
+        upgrade_numbers: &AHashMap<(String, String), i64>,
+        txn_version: i64,
+        upgrade_changes: &[(usize, &WriteSetChange)],
+    ) -> Result<(Vec<Self>, Vec<UpgradeInconsistency>), (usize, anyhow::Error)> {
+        let contract_address = contract.address.as_str();
+        // Every module written at the address, indexed or not, to check the upgraded packages
+        // against
+        let mut raw_module_changes: AHashMap<String, (usize, MoveModuleBytecode)> = AHashMap::new();
+        let mut raw_package_changes: Vec<(usize, PackageUpgradeChangeOnChain)> = vec![];
 
-        let module_changes = raw_package_changes
-            .iter()
-            .flat_map(|package_change| package_change.packages.clone())
-            .map(|package| {
-                package
+        for (change_idx, change) in upgrade_changes {
+            match change.change.as_ref() {
+                Some(Change::WriteModule(write_module_change)) => {
+                    let module_bytecode = write_module_change
+                        .data
+                        .clone()
+                        .context("MoveModuleBytecode data is missing")
+                        .map_err(|e| (*change_idx, e))?;
+                    let module_name = module_bytecode
+                        .abi
+                        .as_ref()
+                        .context("MoveModuleBytecode abi is missing")
+                        .map_err(|e| (*change_idx, e))?
+                        .name
+                        .clone();
+                    raw_module_changes.insert(module_name, (*change_idx, module_bytecode));
+                }
+                Some(Change::WriteResource(write_resource_change)) => {
+                    let package_upgrade: PackageUpgradeChangeOnChain =
+                        serde_json::from_str(write_resource_change.data.as_str())
+                            .context("Failed to parse PackageUpgradeChangeOnChain")
+                            .map_err(|e| (*change_idx, e))?;
+                    raw_package_changes.push((*change_idx, package_upgrade));
+                }
+                _ => {}
+            }
+        }
+
+        let mut package_changes = vec![];
+        let mut module_changes = vec![];
+        let mut inconsistencies = vec![];
+        let mut listed_modules: AHashSet<&str> = AHashSet::new();
+        for (change_idx, package_change) in raw_package_changes.iter() {
+            for package in package_change.packages.iter() {
+                listed_modules.extend(package.modules.iter().map(|module| module.name.as_str()));
+                let upgrade_number = package
+                    .parse_upgrade_number()
+                    .map_err(|e| (*change_idx, e))?;
+                // The registry lists every package at the address, republishing a package
+                // increments its upgrade_number
+                let upgraded = match upgrade_numbers
+                    .get(&(contract_address.to_string(), package.name.clone()))
+                {
+                    Some(previous_upgrade_number) => upgrade_number > *previous_upgrade_number,
+                    None => package
+                        .modules
+                        .iter()
+                        .any(|module| raw_module_changes.contains_key(&module.name)),
+                };
+                if !upgraded {
+                    continue;
+                }
+                // Only keep the packages that contain at least one module to index
+                let modules = package
                     .modules
                     .iter()
-                    .map(|module| {
-                        let raw_module = raw_module_changes
-                            .get(&(contract_address.to_string(), module.name.clone()))
-                            .unwrap_or_else(|| {
-                                panic!("Module bytecode not found for module {}", module.name)
-                            });
-                        ModuleUpgrade {
-                            module_addr: contract_address.to_string(),
-                            module_name: module.name.clone(),
-                            upgrade_number: package.upgrade_number.parse().unwrap(),
-                            module_bytecode: raw_module.bytecode.clone(),
-                            module_source_code: module.source.clone(),
-                            module_abi: serde_json::json!(raw_module.abi.clone().unwrap_or_else(
-                                || { panic!("Module abi is missing for module {}", module.name) }
-                            )),
-                            tx_version: txn_version,
-                        }
-                    })
-                    .collect::<Vec<ModuleUpgrade>>()
+                    .filter(|module| contract.includes_module(module.name.as_str()))
+                    .collect::<Vec<_>>();
+                if modules.is_empty() {
+                    continue;
+                }
+                let missing_modules = modules
+                    .iter()
+                    .filter(|module| !raw_module_changes.contains_key(&module.name))
+                    .map(|module| module.name.as_str())
+                    .collect::<Vec<_>>();
+                if !missing_modules.is_empty() {
+                    inconsistencies.push((
+                        *change_idx,
+                        format!(
+                            "Module bytecode not found for modules {} of package {}",
+                            missing_modules.join(", "),
+                            package.name
+                        ),
+                    ));
+                    continue;
+                }
+
+                package_changes.push(
+                    package
+                        .to_db_package_upgrade(txn_version, contract_address.to_string())
+                        .map_err(|e| (*change_idx, e))?,
+                );
+                for module in modules {
+                    let (module_change_idx, raw_module) = &raw_module_changes[&module.name];
+                    let module_abi = raw_module
+                        .abi
+                        .clone()
+                        .with_context(|| {
+                            format!("Module abi is missing for module {}", module.name)
+                        })
+                        .map_err(|e| (*module_change_idx, e))?;
+                    module_changes.push(ModuleUpgrade {
+                        module_addr: contract_address.to_string(),
+                        module_name: module.name.clone(),
+                        upgrade_number,
+                        module_bytecode: raw_module.bytecode.clone(),
+                        module_source_code: module.source.clone(),
+                        module_abi: serde_json::json!(module_abi),
+                        tx_version: txn_version,
+                        decoded_source: module.decode_source().map_err(|e| (*change_idx, e))?,
+                        source_available: module.source_available(),
+                    });
+                }
+            }
+        }
+
+        // Modules to index written without the package registry listing them can't be
+        // attributed to a package
+        for (module_name, (change_idx, _)) in raw_module_changes.iter() {
+            if contract.includes_module(module_name.as_str())
+                && !listed_modules.contains(module_name.as_str())
+            {
+                inconsistencies.push((
+                    *change_idx,
+                    format!("Module {} is not listed in any package", module_name),
+                ));
+            }
+        }
+
+        Ok((
+            module_changes
+                .into_iter()
+                .map(ContractUpgradeChange::ModuleUpgradeChange)
+                .chain(
+                    package_changes
+                        .into_iter()
+                        .map(ContractUpgradeChange::PackageUpgradeChange),
+                )
+                .collect(),
+            inconsistencies,
+        ))
+    }
+}
+
+/// Whether the change writes a module or the package registry under the contract address.
+fn is_upgrade_change(contract_address: &str, change: &WriteSetChange) -> bool {
+    match change.change.as_ref() {
+        Some(Change::WriteModule(write_module_change)) => {
+            standardize_address(write_module_change.address.as_str()) == contract_address
+        }
+        Some(Change::WriteResource(write_resource_change)) => {
+            standardize_address(write_resource_change.address.as_str()) == contract_address
+                && write_resource_change.type_str == "0x1::code::PackageRegistry"
+        }
+        _ => false,
+    }
+}
+
+fn change_type_str(change: &WriteSetChange) -> String {
+    match change.change.as_ref() {
+        Some(Change::WriteModule(write_module_change)) => match write_module_change
+            .data
+            .as_ref()
+            .and_then(|data| data.abi.as_ref())
+        {
+            Some(abi) => format!("{}::{}", write_module_change.address, abi.name),
+            None => write_module_change.address.clone(),
+        },
+        Some(Change::WriteResource(write_resource_change)) => {
+            write_resource_change.type_str.clone()
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Changes of a transaction that publishes `module_names` under 0xcafe, with a registry
+    /// listing the message_board package at `upgrade_number`.
+    fn upgrade_changes(module_names: &[&str], upgrade_number: &str) -> Vec<WriteSetChange> {
+        let registry = serde_json::json!({
+            "packages": [{
+                "manifest": "0x",
+                "modules": [{ "name": "message_board", "source": "0x" }],
+                "name": "message_board",
+                "source_digest": "",
+                "upgrade_number": upgrade_number,
+                "upgrade_policy": { "policy": 1 },
+            }],
+        });
+        module_names
+            .iter()
+            .map(|module_name| {
+                serde_json::json!({
+                    "type": "TYPE_WRITE_MODULE",
+                    "writeModule": {
+                        "address": "0xcafe",
+                        "data": {
+                            "bytecode": "oRzrCw==",
+                            "abi": { "address": "0xcafe", "name": module_name },
+                        },
+                    },
+                })
             })
-            .flatten()
-            .collect::<Vec<ModuleUpgrade>>();
+            .chain(std::iter::once(serde_json::json!({
+                "type": "TYPE_WRITE_RESOURCE",
+                "writeResource": {
+                    "address": "0xcafe",
+                    "typeStr": "0x1::code::PackageRegistry",
+                    "data": registry.to_string(),
+                },
+            })))
+            .map(|change| serde_json::from_value(change).unwrap())
+            .collect()
+    }
 
-        module_changes
+    fn upgraded_packages(
+        upgrade_numbers: &AHashMap<(String, String), i64>,
+        changes: &[WriteSetChange],
+    ) -> Vec<(String, i64)> {
+        let contracts = [ContractAddressConfig {
+            address: standardize_address("0xcafe"),
+            modules: None,
+        }];
+        let upgrades = ContractUpgradeChange::from_changes(&contracts, upgrade_numbers, 1, changes);
+        assert!(upgrades.failed_items.is_empty());
+        assert!(upgrades.inconsistent_items.is_empty());
+        upgrades
+            .changes
             .into_iter()
-            .map(ContractUpgradeChange::ModuleUpgradeChange)
-            .chain(
-                package_changes
-                    .into_iter()
-                    .map(ContractUpgradeChange::PackageUpgradeChange),
-            )
+            .filter_map(|change| match change {
+                ContractUpgradeChange::PackageUpgradeChange(package) => {
+                    Some((package.package_name, package.upgrade_number))
+                }
+                ContractUpgradeChange::ModuleUpgradeChange(_) => None,
+            })
             .collect()
     }
+
+    #[test]
+    fn test_records_packages_whose_upgrade_number_increased() {
+        let key = (standardize_address("0xcafe"), "message_board".to_string());
+        let changes = upgrade_changes(&["message_board"], "2");
+
+        let upgrade_numbers = AHashMap::from([(key.clone(), 1)]);
+        assert_eq!(
+            upgraded_packages(&upgrade_numbers, &changes),
+            vec![("message_board".to_string(), 2)]
+        );
+        // Written again without a new upgrade_number
+        let upgrade_numbers = AHashMap::from([(key, 2)]);
+        assert!(upgraded_packages(&upgrade_numbers, &changes).is_empty());
+
+        // A package not seen yet is upgraded if its modules were written
+        assert_eq!(
+            upgraded_packages(&AHashMap::new(), &changes),
+            vec![("message_board".to_string(), 2)]
+        );
+        assert!(upgraded_packages(&AHashMap::new(), &upgrade_changes(&[], "2")).is_empty());
+    }
 }
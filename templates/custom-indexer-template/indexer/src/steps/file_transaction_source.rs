@@ -0,0 +1,502 @@
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::Transaction,
+    traits::{NamedStep, PollableAsyncRunType, PollableAsyncStep, Processable},
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+    utils::errors::ProcessorError,
+};
+use async_trait::async_trait;
+use flate2::bufread::GzDecoder;
+use prost::Message;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::transaction_source_config::{FileSourceConfig, TransactionFileFormat},
+    steps::recorder::RecordedBatch,
+};
+
+enum OpenFile {
+    Plain(BufReader<File>),
+    // Gzip members are read one at a time, the recorder archives each batch as a member
+    Gzip(BufReader<GzDecoder<BufReader<File>>>),
+}
+
+/// Reads transactions one at a time from a file or the files of a directory. Files ending in
+/// `.gz` are decompressed, like the archives written by the recorder.
+pub struct TransactionFileReader {
+    format: TransactionFileFormat,
+    // Files that haven't been opened yet, in read order
+    files: VecDeque<PathBuf>,
+    current: Option<(PathBuf, OpenFile)>,
+    // Batch the gzip member being read was recorded as, when written by the recorder
+    recorded_batch: Option<RecordedBatch>,
+}
+
+impl TransactionFileReader {
+    pub fn new(path: &Path, format: TransactionFileFormat) -> Result<Self> {
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to list {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Failed to list {}", path.display()))?;
+            // Hidden files are skipped, e.g. the archive file the recorder is still writing
+            files.retain(|file| {
+                file.is_file()
+                    && !file
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            });
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        Ok(Self {
+            format,
+            files: files.into(),
+            current: None,
+            recorded_batch: None,
+        })
+    }
+
+    fn open(&mut self, path: &Path) -> Result<OpenFile> {
+        let file = BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        if path.extension().is_some_and(|extension| extension == "gz") {
+            Ok(self.open_gzip_member(file))
+        } else {
+            Ok(OpenFile::Plain(file))
+        }
+    }
+
+    fn open_gzip_member(&mut self, file: BufReader<File>) -> OpenFile {
+        let decoder = GzDecoder::new(file);
+        self.recorded_batch = decoder
+            .header()
+            .and_then(|header| header.comment())
+            .and_then(|comment| serde_json::from_slice(comment).ok());
+        OpenFile::Gzip(BufReader::new(decoder))
+    }
+
+    /// Next transaction across all files, None once every file has been read.
+    pub fn next_transaction(&mut self) -> Result<Option<Transaction>> {
+        loop {
+            if self.current.is_none() {
+                let Some(path) = self.files.pop_front() else {
+                    return Ok(None);
+                };
+                let file = self.open(&path)?;
+                self.current = Some((path, file));
+            }
+            let (path, file) = self.current.as_mut().unwrap();
+            let reader: &mut dyn BufRead = match file {
+                OpenFile::Plain(reader) => reader,
+                OpenFile::Gzip(reader) => reader,
+            };
+            let transaction = match self.format {
+                TransactionFileFormat::JsonLines => read_json_line(reader),
+                TransactionFileFormat::LengthPrefixedProtobuf => read_length_prefixed(reader),
+            }
+            .with_context(|| format!("Failed to read a transaction from {}", path.display()))?;
+            if transaction.is_some() {
+                return Ok(transaction);
+            }
+
+            // End of the file, or of a gzip member that may be followed by another one
+            let (path, file) = self.current.take().unwrap();
+            self.recorded_batch = None;
+            if let OpenFile::Gzip(reader) = file {
+                let mut file = reader.into_inner().into_inner();
+                if !file
+                    .fill_buf()
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+                    .is_empty()
+                {
+                    let file = self.open_gzip_member(file);
+                    self.current = Some((path, file));
+                }
+            }
+        }
+    }
+
+    /// Batch the last transaction returned was recorded in, for archives of the recorder.
+    pub fn recorded_batch(&self) -> Option<&RecordedBatch> {
+        self.recorded_batch.as_ref()
+    }
+}
+
+fn read_json_line(reader: &mut dyn BufRead) -> Result<Option<Transaction>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            return serde_json::from_str(&line)
+                .map(Some)
+                .context("Invalid transaction JSON");
+        }
+    }
+}
+
+fn read_length_prefixed(reader: &mut dyn BufRead) -> Result<Option<Transaction>> {
+    // A clean end of file is only allowed between transactions
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => anyhow::anyhow!("File ends in a length prefix"),
+        _ => e.into(),
+    })?;
+    let mut buf = vec![0u8; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => anyhow::anyhow!("File ends in a transaction"),
+        _ => e.into(),
+    })?;
+    Transaction::decode(buf.as_slice())
+        .map(Some)
+        .context("Invalid transaction protobuf")
+}
+
+/// FileTransactionSource is a step that reads transactions from local files, in place of the
+/// gRPC transaction stream. Like the stream, it emits batches that cover contiguous version
+/// ranges and stops after the ending version. Recorder archives are replayed in the batches
+/// they were recorded in.
+pub struct FileTransactionSource
+where
+    Self: Sized + Send + 'static,
+{
+    reader: TransactionFileReader,
+    batch_size: usize,
+    // First version of the next batch
+    next_version: u64,
+    ending_version: Option<u64>,
+    // Highest version read so far, to reject files that aren't in version order
+    last_read_version: Option<u64>,
+    // Transaction read past the end of the previous recorded batch
+    pending: Option<(Transaction, Option<RecordedBatch>)>,
+    end_of_stream: bool,
+}
+
+impl FileTransactionSource {
+    pub fn new(config: &FileSourceConfig, starting_version: u64) -> Result<Self> {
+        if config.batch_size == 0 {
+            anyhow::bail!("transaction_source.batch_size must be greater than 0");
+        }
+        Ok(Self {
+            reader: TransactionFileReader::new(&config.path, config.format)?,
+            batch_size: config.batch_size,
+            next_version: starting_version,
+            ending_version: config.ending_version,
+            last_read_version: None,
+            pending: None,
+            end_of_stream: false,
+        })
+    }
+
+    fn next_transaction(
+        &mut self,
+    ) -> Result<Option<(Transaction, Option<RecordedBatch>)>, ProcessorError> {
+        if let Some(pending) = self.pending.take() {
+            return Ok(Some(pending));
+        }
+        let transaction =
+            self.reader
+                .next_transaction()
+                .map_err(|e| ProcessorError::PollError {
+                    message: format!("{:#}", e),
+                })?;
+        let Some(transaction) = transaction else {
+            return Ok(None);
+        };
+        if self
+            .last_read_version
+            .is_some_and(|last_read_version| transaction.version <= last_read_version)
+        {
+            return Err(ProcessorError::PollError {
+                message: format!(
+                    "Transaction files aren't in version order, version {} comes after {}",
+                    transaction.version,
+                    self.last_read_version.unwrap()
+                ),
+            });
+        }
+        self.last_read_version = Some(transaction.version);
+        Ok(Some((transaction, self.reader.recorded_batch().cloned())))
+    }
+
+    /// Next batch of transactions, with the batch they were recorded in if any. Transactions
+    /// that weren't recorded are batched by batch_size.
+    fn read_batch(&mut self) -> Result<(Vec<Transaction>, Option<RecordedBatch>), ProcessorError> {
+        let mut transactions = vec![];
+        let mut batch = None;
+        while batch.is_some() || transactions.len() < self.batch_size {
+            let Some((transaction, recorded_batch)) = self.next_transaction()? else {
+                self.end_of_stream = true;
+                break;
+            };
+            if transaction.version < self.next_version {
+                continue;
+            }
+            if self
+                .ending_version
+                .is_some_and(|ending_version| transaction.version > ending_version)
+            {
+                self.end_of_stream = true;
+                break;
+            }
+            if !transactions.is_empty() && recorded_batch != batch {
+                self.pending = Some((transaction, recorded_batch));
+                break;
+            }
+            batch = recorded_batch;
+            transactions.push(transaction);
+        }
+        Ok((transactions, batch))
+    }
+}
+
+impl NamedStep for FileTransactionSource {
+    fn name(&self) -> String {
+        "FileTransactionSource".to_string()
+    }
+}
+
+#[async_trait]
+impl Processable for FileTransactionSource {
+    type Input = ();
+    type Output = Vec<Transaction>;
+    type RunType = PollableAsyncRunType;
+
+    async fn process(
+        &mut self,
+        _item: TransactionContext<()>,
+    ) -> Result<Option<TransactionContext<Vec<Transaction>>>, ProcessorError> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl PollableAsyncStep for FileTransactionSource
+where
+    Self: Sized + Send + Sync + 'static,
+{
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(0)
+    }
+
+    async fn poll(
+        &mut self,
+    ) -> Result<Option<Vec<TransactionContext<Vec<Transaction>>>>, ProcessorError> {
+        if self.end_of_stream {
+            return Ok(None);
+        }
+        // Reading is blocking file IO, let the runtime move other tasks off this worker
+        let (transactions, recorded_batch) = tokio::task::block_in_place(|| self.read_batch())?;
+
+        let (Some(first), Some(last)) = (transactions.first(), transactions.last()) else {
+            return Ok(None);
+        };
+        let metadata = match recorded_batch {
+            // Replayed as recorded, unless the starting or ending version cuts the batch short
+            Some(batch)
+                if batch.start_version >= self.next_version
+                    && self
+                        .ending_version
+                        .is_none_or(|ending_version| ending_version >= batch.end_version) =>
+            {
+                TransactionMetadata {
+                    start_version: self.next_version,
+                    ..TransactionMetadata::from(batch)
+                }
+            }
+            _ => TransactionMetadata {
+                // Batches cover every version since the previous one, like the gRPC stream, so
+                // versions missing from the files don't look like a gap to the version tracker
+                start_version: self.next_version,
+                end_version: last.version,
+                start_transaction_timestamp: first.timestamp.clone(),
+                end_transaction_timestamp: last.timestamp.clone(),
+                total_size_in_bytes: transactions
+                    .iter()
+                    .map(|transaction| transaction.encoded_len() as u64)
+                    .sum(),
+            },
+        };
+        let end_version = metadata.end_version;
+        self.next_version = end_version + 1;
+        Ok(Some(vec![TransactionContext {
+            data: transactions,
+            metadata,
+        }]))
+    }
+
+    async fn should_continue_polling(&mut self) -> bool {
+        if self.end_of_stream {
+            tracing::info!(
+                next_version = self.next_version,
+                "Reached the end of the transaction files"
+            );
+        }
+        !self.end_of_stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+
+    fn transaction(version: u64) -> Transaction {
+        Transaction {
+            version,
+            ..Default::default()
+        }
+    }
+
+    fn json_lines(versions: &[u64]) -> String {
+        versions
+            .iter()
+            .map(|version| serde_json::to_string(&transaction(*version)).unwrap() + "\n")
+            .collect()
+    }
+
+    fn length_prefixed(versions: &[u64]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for version in versions {
+            let encoded = transaction(*version).encode_to_vec();
+            bytes.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&encoded);
+        }
+        bytes
+    }
+
+    /// Empty directory of its own under the system temp dir.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "file_transaction_source_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_json_line() {
+        let mut reader = Cursor::new(format!("{}\n  \n{}", json_lines(&[1]), json_lines(&[2])));
+        assert_eq!(read_json_line(&mut reader).unwrap().unwrap().version, 1);
+        // Blank lines are skipped
+        assert_eq!(read_json_line(&mut reader).unwrap().unwrap().version, 2);
+        assert!(read_json_line(&mut reader).unwrap().is_none());
+
+        assert!(read_json_line(&mut Cursor::new("{\"version\":")).is_err());
+    }
+
+    #[test]
+    fn test_read_length_prefixed() {
+        let bytes = length_prefixed(&[1, 2]);
+        let mut reader = Cursor::new(bytes.clone());
+        assert_eq!(
+            read_length_prefixed(&mut reader).unwrap().unwrap().version,
+            1
+        );
+        assert_eq!(
+            read_length_prefixed(&mut reader).unwrap().unwrap().version,
+            2
+        );
+        assert!(read_length_prefixed(&mut reader).unwrap().is_none());
+
+        let error = read_length_prefixed(&mut Cursor::new(&bytes[..2])).unwrap_err();
+        assert!(error.to_string().contains("length prefix"), "{}", error);
+        let error = read_length_prefixed(&mut Cursor::new(&bytes[..5])).unwrap_err();
+        assert!(
+            error.to_string().contains("ends in a transaction"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_reads_directory_in_file_name_order() {
+        let dir = test_dir("directory");
+        let mut gz = GzEncoder::new(
+            File::create(dir.join("a.json.gz")).unwrap(),
+            Compression::default(),
+        );
+        gz.write_all(json_lines(&[1, 2]).as_bytes()).unwrap();
+        gz.finish().unwrap();
+        std::fs::write(dir.join("b.json"), json_lines(&[3])).unwrap();
+        // Hidden files are skipped
+        std::fs::write(dir.join(".c.json"), json_lines(&[4])).unwrap();
+
+        let mut reader =
+            TransactionFileReader::new(&dir, TransactionFileFormat::JsonLines).unwrap();
+        let mut versions = vec![];
+        while let Some(transaction) = reader.next_transaction().unwrap() {
+            versions.push(transaction.version);
+        }
+        assert_eq!(versions, vec![1, 2, 3]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn file_source(dir: &Path, versions: &[u64], starting_version: u64) -> FileTransactionSource {
+        let path = dir.join(format!("from_{}.bin", starting_version));
+        std::fs::write(&path, length_prefixed(versions)).unwrap();
+        FileTransactionSource::new(
+            &FileSourceConfig {
+                path,
+                format: TransactionFileFormat::LengthPrefixedProtobuf,
+                starting_version: None,
+                ending_version: Some(4),
+                batch_size: 2,
+                chain_id: None,
+            },
+            starting_version,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_batch() {
+        let dir = test_dir("read_batch");
+        let mut source = file_source(&dir, &[1, 2, 3, 5, 6], 2);
+        let batch = |source: &mut FileTransactionSource| {
+            source
+                .read_batch()
+                .unwrap()
+                .0
+                .iter()
+                .map(|transaction| transaction.version)
+                .collect::<Vec<_>>()
+        };
+        // Versions before the starting version are skipped, and reading stops after the
+        // ending version
+        assert_eq!(batch(&mut source), vec![2, 3]);
+        assert!(!source.end_of_stream);
+        assert!(batch(&mut source).is_empty());
+        assert!(source.end_of_stream);
+
+        let mut source = file_source(&dir, &[1, 3, 2], 0);
+        assert_eq!(batch(&mut source), vec![1, 3]);
+        let error = source.read_batch().unwrap_err();
+        assert!(
+            format!("{:?}", error).contains("version 2 comes after 3"),
+            "{:?}",
+            error
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
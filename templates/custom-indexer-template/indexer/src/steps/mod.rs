@@ -1,4 +1,8 @@
+pub mod event_handler;
 pub mod extractor;
+pub mod file_transaction_source;
 pub mod storer;
 pub mod processor;
+pub mod recorder;
+pub mod redrive;
 pub mod storers;
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aptos_indexer_processor_sdk::{
     aptos_indexer_transaction_stream::{TransactionStream, TransactionStreamConfig},
     builder::ProcessorBuilder,
@@ -6,14 +6,22 @@ use aptos_indexer_processor_sdk::{
     traits::IntoRunnableStep,
 };
 
-use super::{extractor::Extractor, storer::Storer};
+use super::{
+    extractor::Extractor, file_transaction_source::FileTransactionSource, recorder::Recorder,
+    storer::Storer, storers::upgrade_package_change_storer::get_package_upgrade_numbers,
+};
 use crate::{
-    config::indexer_processor_config::IndexerProcessorConfig,
+    config::{
+        indexer_processor_config::IndexerProcessorConfig, processor_config::ProcessorConfig,
+        transaction_source_config::TransactionSourceConfig,
+    },
     utils::{
         chain_id::check_or_update_chain_id, database_connection::new_db_pool,
-        database_utils::ArcDbPool, latest_processed_version_tracker::LatestVersionProcessedTracker,
-        starting_version::get_starting_version,
+        database_migrations::run_pending_migrations, database_utils::ArcDbPool,
+        latest_processed_version_tracker::LatestVersionProcessedTracker,
+        pipeline_state::PIPELINE_STATE, starting_version::get_starting_version,
     },
+    webhook_delivery::WebhookDeliveryWorker,
 };
 
 pub struct ContractProcessor {
@@ -36,6 +44,22 @@ impl ContractProcessor {
     }
 
     pub async fn run_processor(self) -> Result<()> {
+        if self.config.db_config.run_migrations_on_startup {
+            run_pending_migrations(&self.config.db_config).await?;
+        }
+
+        PIPELINE_STATE.register(
+            self.db_pool.clone(),
+            self.config.processor_config.name().to_string(),
+            self.config.readiness_config.clone(),
+        );
+
+        if let Some(webhook_config) = &self.config.webhook_config {
+            tokio::spawn(
+                WebhookDeliveryWorker::new(self.db_pool.clone(), webhook_config.clone()).run(),
+            );
+        }
+
         // Merge the starting version from config and the latest processed version from the DB
         let starting_version = get_starting_version(&self.config, self.db_pool.clone()).await?;
 
@@ -44,21 +68,66 @@ impl ContractProcessor {
             starting_version
         );
 
-        // Check and update the ledger chain id to ensure we're indexing the correct chain
-        let grpc_chain_id = TransactionStream::new(self.config.transaction_stream_config.clone())
-            .await?
-            .get_chain_id()
-            .await?;
-        check_or_update_chain_id(grpc_chain_id as i64, self.db_pool.clone()).await?;
+        // Check and update the ledger chain id to ensure we're indexing the correct chain, then
+        // define the step that feeds transactions to the rest of the processor
+        let processor_builder = match &self.config.transaction_source {
+            TransactionSourceConfig::Grpc => {
+                let transaction_stream_config =
+                    self.config.transaction_stream_config.clone().context(
+                        "transaction_stream_config is required when transaction_source is grpc",
+                    )?;
+                let grpc_chain_id = TransactionStream::new(transaction_stream_config.clone())
+                    .await?
+                    .get_chain_id()
+                    .await?;
+                check_or_update_chain_id(grpc_chain_id as i64, self.db_pool.clone()).await?;
+
+                let transaction_stream = TransactionStreamStep::new(TransactionStreamConfig {
+                    starting_version: Some(starting_version),
+                    ..transaction_stream_config
+                })
+                .await?;
+                ProcessorBuilder::new_with_inputless_first_step(
+                    transaction_stream.into_runnable_step(),
+                )
+            }
+            TransactionSourceConfig::File(file_source_config) => {
+                match file_source_config.chain_id {
+                    Some(chain_id) => {
+                        check_or_update_chain_id(chain_id as i64, self.db_pool.clone()).await?;
+                    }
+                    None => tracing::warn!(
+                        "transaction_source.chain_id is not set, the chain id is not checked"
+                    ),
+                }
+
+                let file_source = FileTransactionSource::new(file_source_config, starting_version)?;
+                ProcessorBuilder::new_with_inputless_first_step(file_source.into_runnable_step())
+            }
+        };
+
+        let processor_builder = match &self.config.recorder_config {
+            Some(recorder_config) => processor_builder.connect_to(
+                Recorder::new(recorder_config.clone())?.into_runnable_step(),
+                10,
+            ),
+            None => processor_builder,
+        };
 
         // Define processor steps
-        let transaction_stream = TransactionStreamStep::new(TransactionStreamConfig {
-            starting_version: Some(starting_version),
-            ..self.config.transaction_stream_config
-        })
-        .await?;
-        let events_extractor = Extractor::new(self.config.contract_config.contract_address);
-        let events_storer = Storer::new(self.db_pool.clone());
+        let ProcessorConfig::ContractProcessor(processor_config) = &self.config.processor_config;
+        let events_extractor = Extractor::new(
+            &self.config.contract_config,
+            processor_config.on_decode_error,
+        )
+        .with_upgrade_numbers(get_package_upgrade_numbers(&self.db_pool).await?);
+        let events_storer = Storer::new(
+            self.db_pool.clone(),
+            self.config.contract_config.event_handlers(),
+            self.config.points_config.clone(),
+            self.config.leaderboard_config.clone(),
+            self.config.webhook_config.clone(),
+        );
         let version_tracker = LatestVersionProcessedTracker::new(
             self.config.db_config,
             starting_version,
@@ -67,19 +136,17 @@ impl ContractProcessor {
         .await?;
 
         // Connect processor steps together
-        let (_, buffer_receiver) = ProcessorBuilder::new_with_inputless_first_step(
-            transaction_stream.into_runnable_step(),
-        )
-        .connect_to(events_extractor.into_runnable_step(), 10)
-        .connect_to(events_storer.into_runnable_step(), 10)
-        .connect_to(version_tracker.into_runnable_step(), 10)
-        .end_and_return_output_receiver(10);
+        let (_, buffer_receiver) = processor_builder
+            .connect_to(events_extractor.into_runnable_step(), 10)
+            .connect_to(events_storer.into_runnable_step(), 10)
+            .connect_to(version_tracker.into_runnable_step(), 10)
+            .end_and_return_output_receiver(10);
 
         // (Optional) Parse the results
         loop {
             match buffer_receiver.recv().await {
                 Ok(txn_context) => {
-                    if txn_context.data.events.len() == 0 && txn_context.data.changes.len() == 0 {
+                    if txn_context.data.is_empty() {
                         continue;
                     }
                     tracing::info!(
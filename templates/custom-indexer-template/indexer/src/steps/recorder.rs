@@ -0,0 +1,529 @@
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::{transaction::v1::Transaction, util::timestamp::Timestamp},
+    traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+    utils::errors::ProcessorError,
+};
+use async_trait::async_trait;
+use flate2::{bufread::GzDecoder, Compression, GzBuilder};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::transaction_source_config::RecorderConfig;
+
+const ARCHIVE_FILE_PREFIX: &str = "transactions_";
+const ARCHIVE_FILE_EXTENSION: &str = ".pb.gz";
+
+/// Name of a finished archive file. Versions are zero padded so that file name order is
+/// version order, which is the order the file transaction source reads a directory in.
+fn archive_file_name(start_version: u64, end_version: u64) -> String {
+    format!(
+        "{}{:020}_{:020}{}",
+        ARCHIVE_FILE_PREFIX, start_version, end_version, ARCHIVE_FILE_EXTENSION
+    )
+}
+
+/// Version range of a finished archive file, None for other files.
+fn parse_archive_file_name(file_name: &str) -> Option<(u64, u64)> {
+    let versions = file_name
+        .strip_prefix(ARCHIVE_FILE_PREFIX)?
+        .strip_suffix(ARCHIVE_FILE_EXTENSION)?;
+    let (start_version, end_version) = versions.split_once('_')?;
+    Some((start_version.parse().ok()?, end_version.parse().ok()?))
+}
+
+/// Metadata of a batch as the processor received it. Each batch is archived as a gzip member
+/// of its own with this in the member's header comment, so a replay can emit the same batches.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RecordedBatch {
+    pub start_version: u64,
+    pub end_version: u64,
+    pub start_transaction_timestamp: Option<Timestamp>,
+    pub end_transaction_timestamp: Option<Timestamp>,
+    pub total_size_in_bytes: u64,
+}
+
+impl From<&TransactionMetadata> for RecordedBatch {
+    fn from(metadata: &TransactionMetadata) -> Self {
+        Self {
+            start_version: metadata.start_version,
+            end_version: metadata.end_version,
+            start_transaction_timestamp: metadata.start_transaction_timestamp.clone(),
+            end_transaction_timestamp: metadata.end_transaction_timestamp.clone(),
+            total_size_in_bytes: metadata.total_size_in_bytes,
+        }
+    }
+}
+
+impl From<RecordedBatch> for TransactionMetadata {
+    fn from(batch: RecordedBatch) -> Self {
+        Self {
+            start_version: batch.start_version,
+            end_version: batch.end_version,
+            start_transaction_timestamp: batch.start_transaction_timestamp,
+            end_transaction_timestamp: batch.end_transaction_timestamp,
+            total_size_in_bytes: batch.total_size_in_bytes,
+        }
+    }
+}
+
+/// Counts the bytes passed through the underlying reader or writer, i.e. compressed bytes.
+struct ByteCounter<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<W: Write> Write for ByteCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for ByteCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Writes a batch as a gzip member of length-prefixed protobuf transactions.
+fn write_batch<W: Write>(
+    writer: W,
+    batch: &RecordedBatch,
+    transactions: &[&Transaction],
+) -> Result<W> {
+    let mut encoder = GzBuilder::new()
+        .comment(serde_json::to_vec(batch)?)
+        .write(writer, Compression::default());
+    for transaction in transactions {
+        let encoded = transaction.encode_to_vec();
+        encoder.write_all(&(encoded.len() as u32).to_be_bytes())?;
+        encoder.write_all(&encoded)?;
+    }
+    Ok(encoder.finish()?)
+}
+
+/// Finishes an archive file left hidden by a run that didn't shut down cleanly. The file is
+/// cut after its last complete batch and renamed like a finished archive, returning its
+/// version range, or removed when not even the first batch is complete.
+fn recover_partial_archive(path: &Path) -> Result<Option<(u64, u64)>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(ByteCounter {
+        inner: file,
+        count: 0,
+    });
+    let mut versions: Option<(u64, u64)> = None;
+    let mut complete_len = 0;
+    while !reader.fill_buf()?.is_empty() {
+        let mut decoder = GzDecoder::new(reader);
+        let batch = decoder
+            .header()
+            .and_then(|header| header.comment())
+            .and_then(|comment| serde_json::from_slice::<RecordedBatch>(comment).ok());
+        // Reading the member to the end checks its trailer, which is missing when it's cut off
+        let complete = std::io::copy(&mut decoder, &mut std::io::sink()).is_ok();
+        reader = decoder.into_inner();
+        let (Some(batch), true) = (batch, complete) else {
+            break;
+        };
+        complete_len = reader.get_ref().count - reader.buffer().len() as u64;
+        versions = Some((
+            versions.map_or(batch.start_version, |(start_version, _)| start_version),
+            batch.end_version,
+        ));
+    }
+
+    let Some((start_version, end_version)) = versions else {
+        tracing::warn!(
+            file = %path.display(),
+            "Removing partial archive file without a complete batch"
+        );
+        std::fs::remove_file(path)?;
+        return Ok(None);
+    };
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(complete_len)?;
+    file.sync_all()?;
+    let finished_path = path.with_file_name(archive_file_name(start_version, end_version));
+    std::fs::rename(path, &finished_path)
+        .with_context(|| format!("Failed to finish {}", finished_path.display()))?;
+    tracing::warn!(
+        file = %finished_path.display(),
+        start_version,
+        end_version,
+        "Finished partial archive file left by the previous run"
+    );
+    Ok(Some((start_version, end_version)))
+}
+
+/// Archive file that is still being written. It's hidden until it's finished, so the file
+/// transaction source never reads a partial file.
+struct OpenArchive {
+    path: PathBuf,
+    writer: ByteCounter<BufWriter<File>>,
+    start_version: u64,
+    end_version: u64,
+}
+
+/// Recorder is a step that archives every batch of transactions it receives before passing it
+/// on. Archives are gzipped, length-prefixed protobuf transactions, so the archive directory
+/// can be read back by the file transaction source, which replays the recorded batches.
+pub struct Recorder
+where
+    Self: Sized + Send + 'static,
+{
+    config: RecorderConfig,
+    current: Option<OpenArchive>,
+    // Highest version already archived, transactions up to it are not written again when the
+    // processor restarts from an earlier version
+    last_archived_version: Option<u64>,
+}
+
+impl AsyncStep for Recorder {}
+
+impl NamedStep for Recorder {
+    fn name(&self) -> String {
+        "Recorder".to_string()
+    }
+}
+
+impl Recorder {
+    pub fn new(config: RecorderConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.archive_dir).with_context(|| {
+            format!(
+                "Failed to create the archive directory {}",
+                config.archive_dir.display()
+            )
+        })?;
+        let mut last_archived_version = None;
+        for entry in std::fs::read_dir(&config.archive_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let versions = if file_name.starts_with('.') && file_name.contains(ARCHIVE_FILE_PREFIX)
+            {
+                recover_partial_archive(&entry.path())?
+            } else {
+                parse_archive_file_name(&file_name)
+            };
+            if let Some((_, end_version)) = versions {
+                last_archived_version = last_archived_version.max(Some(end_version));
+            }
+        }
+        Ok(Self {
+            config,
+            current: None,
+            last_archived_version,
+        })
+    }
+
+    fn record(&mut self, item: &TransactionContext<Vec<Transaction>>) -> Result<()> {
+        let transactions = item
+            .data
+            .iter()
+            .filter(|transaction| {
+                self.last_archived_version
+                    .is_none_or(|version| transaction.version > version)
+            })
+            .collect::<Vec<_>>();
+        let (Some(first), Some(last)) = (transactions.first(), transactions.last()) else {
+            return Ok(());
+        };
+        let batch = match self.last_archived_version {
+            // Only the part of the batch that wasn't archived yet
+            Some(version) if transactions.len() < item.data.len() => RecordedBatch {
+                start_version: version + 1,
+                end_version: item.metadata.end_version.max(last.version),
+                start_transaction_timestamp: first.timestamp.clone(),
+                end_transaction_timestamp: item.metadata.end_transaction_timestamp.clone(),
+                total_size_in_bytes: transactions
+                    .iter()
+                    .map(|transaction| transaction.encoded_len() as u64)
+                    .sum(),
+            },
+            _ => RecordedBatch::from(&item.metadata),
+        };
+
+        if self.current.is_none() {
+            let path = self.config.archive_dir.join(format!(
+                ".{}{:020}{}",
+                ARCHIVE_FILE_PREFIX, batch.start_version, ARCHIVE_FILE_EXTENSION
+            ));
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            self.current = Some(OpenArchive {
+                path,
+                writer: ByteCounter {
+                    inner: BufWriter::new(file),
+                    count: 0,
+                },
+                start_version: batch.start_version,
+                end_version: batch.end_version,
+            });
+        }
+        let archive = self.current.as_mut().unwrap();
+        // Flushed batch by batch, so a crash loses at most the batch being written
+        write_batch(&mut archive.writer, &batch, &transactions)?.flush()?;
+        archive.end_version = batch.end_version;
+        self.last_archived_version = Some(batch.end_version);
+
+        if archive.writer.count >= self.config.max_file_size_bytes {
+            self.finish_archive()?;
+        }
+        Ok(())
+    }
+
+    /// Completes the open archive file under its version range, then applies the size cap.
+    fn finish_archive(&mut self) -> Result<()> {
+        let Some(archive) = self.current.take() else {
+            return Ok(());
+        };
+        archive
+            .writer
+            .inner
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        let path = self.config.archive_dir.join(archive_file_name(
+            archive.start_version,
+            archive.end_version,
+        ));
+        std::fs::rename(&archive.path, &path)
+            .with_context(|| format!("Failed to finish {}", path.display()))?;
+        tracing::info!(
+            file = %path.display(),
+            start_version = archive.start_version,
+            end_version = archive.end_version,
+            "Finished archive file"
+        );
+        self.remove_oldest_archives()
+    }
+
+    fn remove_oldest_archives(&self) -> Result<()> {
+        let mut archives = vec![];
+        for entry in std::fs::read_dir(&self.config.archive_dir)? {
+            let entry = entry?;
+            if parse_archive_file_name(&entry.file_name().to_string_lossy()).is_some() {
+                archives.push((entry.path(), entry.metadata()?.len()));
+            }
+        }
+        archives.sort();
+
+        let mut total_size: u64 = archives.iter().map(|(_, size)| size).sum();
+        for (path, size) in archives {
+            if total_size <= self.config.max_total_size_bytes {
+                break;
+            }
+            tracing::info!(file = %path.display(), "Removing archive file over the size cap");
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            total_size -= size;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Processable for Recorder {
+    type Input = Vec<Transaction>;
+    type Output = Vec<Transaction>;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        item: TransactionContext<Vec<Transaction>>,
+    ) -> Result<Option<TransactionContext<Vec<Transaction>>>, ProcessorError> {
+        // Writing is blocking file IO, let the runtime move other tasks off this worker
+        tokio::task::block_in_place(|| self.record(&item)).map_err(|e| {
+            ProcessorError::ProcessError {
+                message: format!("Failed to archive transactions: {:#}", e),
+            }
+        })?;
+        Ok(Some(item))
+    }
+
+    async fn cleanup(
+        &mut self,
+    ) -> Result<Option<Vec<TransactionContext<Self::Output>>>, ProcessorError> {
+        // Finish the open archive file so it can be replayed
+        tokio::task::block_in_place(|| self.finish_archive()).map_err(|e| {
+            ProcessorError::ProcessError {
+                message: format!("Failed to finish the archive file: {:#}", e),
+            }
+        })?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::transaction_source_config::{FileSourceConfig, TransactionFileFormat},
+        steps::file_transaction_source::{FileTransactionSource, TransactionFileReader},
+    };
+    use aptos_indexer_processor_sdk::traits::PollableAsyncStep;
+
+    /// Empty directory of its own under the system temp dir.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recorder_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn new_recorder(dir: &Path) -> Recorder {
+        Recorder::new(RecorderConfig {
+            archive_dir: dir.to_path_buf(),
+            max_file_size_bytes: RecorderConfig::default_max_file_size_bytes(),
+            max_total_size_bytes: RecorderConfig::default_max_total_size_bytes(),
+        })
+        .unwrap()
+    }
+
+    fn timestamp(version: u64) -> Option<Timestamp> {
+        Some(Timestamp {
+            seconds: version as i64,
+            nanos: 0,
+        })
+    }
+
+    /// Batch as received from the stream, whose versions may start before its first
+    /// transaction and end after its last one.
+    fn batch(
+        start_version: u64,
+        end_version: u64,
+        versions: &[u64],
+    ) -> TransactionContext<Vec<Transaction>> {
+        TransactionContext {
+            data: versions
+                .iter()
+                .map(|version| Transaction {
+                    version: *version,
+                    timestamp: timestamp(*version),
+                    ..Default::default()
+                })
+                .collect(),
+            metadata: TransactionMetadata {
+                start_version,
+                end_version,
+                start_transaction_timestamp: timestamp(versions[0]),
+                end_transaction_timestamp: timestamp(*versions.last().unwrap()),
+                total_size_in_bytes: 1000 * versions.len() as u64,
+            },
+        }
+    }
+
+    fn read_versions(dir: &Path) -> Vec<u64> {
+        let mut reader =
+            TransactionFileReader::new(dir, TransactionFileFormat::LengthPrefixedProtobuf).unwrap();
+        let mut versions = vec![];
+        while let Some(transaction) = reader.next_transaction().unwrap() {
+            versions.push(transaction.version);
+        }
+        versions
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_replays_recorded_batches() {
+        let dir = test_dir("replay");
+        let mut recorder = new_recorder(&dir);
+        recorder.record(&batch(0, 4, &[2, 3])).unwrap();
+        recorder.record(&batch(5, 9, &[5, 8])).unwrap();
+        recorder.finish_archive().unwrap();
+        // After a restart, only the versions that weren't archived yet are written
+        let mut recorder = new_recorder(&dir);
+        recorder.record(&batch(8, 12, &[8, 10, 12])).unwrap();
+        recorder.finish_archive().unwrap();
+        assert!(dir.join(archive_file_name(0, 9)).exists());
+        assert!(dir.join(archive_file_name(10, 12)).exists());
+
+        let mut source = FileTransactionSource::new(
+            &FileSourceConfig {
+                path: dir.clone(),
+                format: TransactionFileFormat::LengthPrefixedProtobuf,
+                starting_version: None,
+                ending_version: None,
+                // Recorded batches are replayed whole, whatever the batch size
+                batch_size: 1,
+                chain_id: None,
+            },
+            0,
+        )
+        .unwrap();
+        let mut batches = vec![];
+        while let Some(contexts) = source.poll().await.unwrap() {
+            for context in contexts {
+                batches.push((
+                    context.metadata.start_version,
+                    context.metadata.end_version,
+                    context
+                        .data
+                        .iter()
+                        .map(|transaction| transaction.version)
+                        .collect::<Vec<_>>(),
+                    context.metadata.total_size_in_bytes,
+                    context.metadata.start_transaction_timestamp,
+                ));
+            }
+        }
+        let recorded = batch(10, 12, &[10, 12]).data;
+        let recorded_size = recorded
+            .iter()
+            .map(|transaction| transaction.encoded_len() as u64)
+            .sum::<u64>();
+        assert_eq!(
+            batches,
+            vec![
+                (0, 4, vec![2, 3], 2000, timestamp(2)),
+                (5, 9, vec![5, 8], 2000, timestamp(5)),
+                (10, 12, vec![10, 12], recorded_size, timestamp(10)),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recovers_partial_archive() {
+        let dir = test_dir("partial");
+        let mut recorder = new_recorder(&dir);
+        recorder.record(&batch(0, 4, &[2, 3])).unwrap();
+        recorder.record(&batch(5, 9, &[5, 8])).unwrap();
+        let path = recorder.current.as_ref().unwrap().path.clone();
+        drop(recorder);
+        // The last batch is cut off, like when the process is killed while writing it
+        let cut_off = batch(10, 12, &[10, 12]);
+        let cut_off = write_batch(
+            vec![],
+            &RecordedBatch::from(&cut_off.metadata),
+            &cut_off.data.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&cut_off[..cut_off.len() / 2]).unwrap();
+        drop(file);
+        // Nothing can be recovered from a file that ends in the first batch
+        let empty_path = dir.join(format!(".{}{:020}.pb.gz", ARCHIVE_FILE_PREFIX, 20));
+        std::fs::write(&empty_path, &cut_off[..cut_off.len() / 2]).unwrap();
+
+        let recorder = new_recorder(&dir);
+        assert_eq!(recorder.last_archived_version, Some(9));
+        assert!(!path.exists());
+        assert!(!empty_path.exists());
+        assert!(dir.join(archive_file_name(0, 9)).exists());
+        assert_eq!(read_versions(&dir), vec![2, 3, 5, 8]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+
+use super::{
+    extractor::Extractor, storer::Storer,
+    storers::upgrade_package_change_storer::get_package_upgrade_numbers,
+};
+use crate::{
+    config::{
+        indexer_processor_config::IndexerProcessorConfig, processor_config::DecodeErrorPolicy,
+    },
+    db_models::failed_item::FailedItemQuery,
+    schema::failed_items,
+    utils::database_connection::{get_db_connection, new_db_pool},
+};
+
+/// Decodes every dead-lettered item again and stores the ones that now succeed, removing them
+/// from the failed_items table. Items that still fail get their error and retry count updated.
+pub async fn redrive_failed_items(config: &IndexerProcessorConfig) -> Result<()> {
+    let pool = new_db_pool(
+        &config.db_config.postgres_connection_string,
+        config.db_config.db_pool_size,
+    )
+    .await;
+    // Upgrades are told apart by the upgrade numbers already stored, as when indexing
+    let extractor = Extractor::new(&config.contract_config, DecodeErrorPolicy::DeadLetter)
+        .with_upgrade_numbers(
+            get_package_upgrade_numbers(&pool)
+                .await
+                .context("Failed to load package upgrade numbers")?,
+        );
+    let storer = Storer::new(
+        pool.clone(),
+        config.contract_config.event_handlers(),
+        config.points_config.clone(),
+        config.leaderboard_config.clone(),
+        config.webhook_config.clone(),
+    );
+
+    let conn = &mut get_db_connection(&pool).await?;
+    let items = FailedItemQuery::get_all(conn)
+        .await
+        .context("Failed to load failed items")?;
+    tracing::info!(count = items.len(), "Re-driving failed items");
+
+    let (mut redriven, mut still_failing) = (0, 0);
+    for item in items {
+        let item_filter = failed_items::table
+            .filter(failed_items::txn_version.eq(item.txn_version))
+            .filter(failed_items::item_type.eq(&item.item_type))
+            .filter(failed_items::item_index.eq(item.item_index));
+        match extractor.redecode(&item) {
+            Ok(data) => {
+                storer.store(data).await?;
+                diesel::delete(item_filter)
+                    .execute(conn)
+                    .await
+                    .context("Failed to delete re-driven item")?;
+                redriven += 1;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    transaction_version = item.txn_version,
+                    item_type = %item.item_type,
+                    item_index = item.item_index,
+                    "Item still fails to decode: {:#}",
+                    e
+                );
+                diesel::update(item_filter)
+                    .set((
+                        failed_items::error.eq(format!("{:#}", e)),
+                        failed_items::retry_count.eq(failed_items::retry_count + 1),
+                    ))
+                    .execute(conn)
+                    .await
+                    .context("Failed to update failed item")?;
+                still_failing += 1;
+            }
+        }
+    }
+
+    tracing::info!(
+        redriven = redriven,
+        still_failing = still_failing,
+        "Finished re-driving failed items"
+    );
+    Ok(())
+}
@@ -6,17 +6,31 @@ use aptos_indexer_processor_sdk::{
     utils::errors::ProcessorError,
 };
 use async_trait::async_trait;
+use std::sync::Arc;
 
 use super::{
+    event_handler::EventHandler,
     extractor::{ContractEvent, ContractUpgradeChange, TransactionContextData},
     storers::{
-        create_message_event_storer::process_create_message_events,
-        update_message_event_storer::process_update_message_events,
+        failed_item_storer::process_failed_items,
+        generic_event_storer::process_generic_events,
+        leaderboard_storer::{get_latest_snapshot_timestamp, process_leaderboard_snapshot},
+        message_revision_storer::process_message_revisions,
+        module_abi_change_storer::process_module_abi_changes,
         upgrade_module_change_storer::process_upgrade_module_changes,
         upgrade_package_change_storer::process_upgrade_package_changes,
     },
 };
-use crate::utils::database_utils::ArcDbPool;
+use crate::{
+    config::{
+        indexer_processor_config::LeaderboardConfig, points_config::PointsConfig,
+        webhook_config::WebhookConfig,
+    },
+    utils::{
+        counters::{observe_db_pool, BATCHES_PROCESSED_COUNT},
+        database_utils::ArcDbPool,
+    },
+};
 
 /// Storer is a step that inserts events in the database.
 pub struct Storer
@@ -24,6 +38,14 @@ where
     Self: Sized + Send + 'static,
 {
     pool: ArcDbPool,
+    // Events are stored handler by handler, in the order EventHandler declares them
+    event_handlers: Vec<EventHandler>,
+    points_config: Arc<PointsConfig>,
+    leaderboard_config: LeaderboardConfig,
+    // Notifications are written to the outbox when set
+    webhook_config: Option<Arc<WebhookConfig>>,
+    // Start of the interval of the latest leaderboard snapshot, loaded from the DB on first use
+    last_snapshot_timestamp: Option<i64>,
 }
 
 impl AsyncStep for Storer {}
@@ -35,49 +57,84 @@ impl NamedStep for Storer {
 }
 
 impl Storer {
-    pub fn new(pool: ArcDbPool) -> Self {
-        Self { pool }
+    pub fn new(
+        pool: ArcDbPool,
+        event_handlers: Vec<EventHandler>,
+        points_config: PointsConfig,
+        leaderboard_config: LeaderboardConfig,
+        webhook_config: Option<WebhookConfig>,
+    ) -> Self {
+        Self {
+            pool,
+            event_handlers,
+            points_config: Arc::new(points_config),
+            leaderboard_config,
+            webhook_config: webhook_config.map(Arc::new),
+            last_snapshot_timestamp: None,
+        }
     }
-}
-
-#[async_trait]
-impl Processable for Storer {
-    type Input = TransactionContextData;
-    type Output = TransactionContextData;
-    type RunType = AsyncRunType;
 
-    async fn process(
+    /// Snapshots the leaderboard when a batch reaches a new snapshot interval. This runs before
+    /// the batch is stored, so the snapshot holds the ranks from before the interval started,
+    /// up to `txn_version`, the version right before the batch.
+    async fn snapshot_leaderboard_if_due(
         &mut self,
-        transaction_context_data: TransactionContext<TransactionContextData>,
-    ) -> Result<Option<TransactionContext<TransactionContextData>>, ProcessorError> {
+        block_timestamp: i64,
+        txn_version: i64,
+    ) -> Result<(), ProcessorError> {
+        let interval = self.leaderboard_config.snapshot_interval_secs as i64;
+        if interval == 0 {
+            return Ok(());
+        }
+        let snapshot_timestamp = block_timestamp - block_timestamp.rem_euclid(interval);
+        if self.last_snapshot_timestamp.is_none() {
+            self.last_snapshot_timestamp = get_latest_snapshot_timestamp(&self.pool).await?;
+        }
+        if self
+            .last_snapshot_timestamp
+            .is_none_or(|last_snapshot_timestamp| last_snapshot_timestamp < snapshot_timestamp)
+        {
+            process_leaderboard_snapshot(self.pool.clone(), snapshot_timestamp, txn_version)
+                .await?;
+            self.last_snapshot_timestamp = Some(snapshot_timestamp);
+        }
+        Ok(())
+    }
+
+    /// Stores everything extracted from a batch of transactions.
+    pub async fn store(&self, data: TransactionContextData) -> Result<(), ProcessorError> {
         let per_table_chunk_sizes: AHashMap<String, usize> = AHashMap::new();
-        let data = transaction_context_data.data.clone();
-        let (create_events, update_events) = data.events.into_iter().fold(
-            (vec![], vec![]),
-            |(mut create_events, mut update_events), event| {
-                match event {
-                    ContractEvent::CreateMessageEvent(message) => {
-                        create_events.push(message);
-                    }
-                    ContractEvent::UpdateMessageEvent(message) => {
-                        update_events.push(message);
-                    }
-                }
-                (create_events, update_events)
-            },
-        );
+        let message_revisions = data
+            .events
+            .iter()
+            .filter_map(ContractEvent::message_revision)
+            .collect::<Vec<_>>();
+        let mut events_by_handler: AHashMap<EventHandler, Vec<ContractEvent>> = AHashMap::new();
+        for event in data.events {
+            events_by_handler
+                .entry(event.handler())
+                .or_default()
+                .push(event);
+        }
 
-        process_create_message_events(
-            self.pool.clone(),
-            per_table_chunk_sizes.clone(),
-            create_events,
-        )
-        .await?;
+        for handler in &self.event_handlers {
+            if let Some(events) = events_by_handler.remove(handler) {
+                handler
+                    .store(
+                        self.pool.clone(),
+                        per_table_chunk_sizes.clone(),
+                        self.points_config.clone(),
+                        self.webhook_config.clone(),
+                        events,
+                    )
+                    .await?;
+            }
+        }
 
-        process_update_message_events(
+        process_message_revisions(
             self.pool.clone(),
             per_table_chunk_sizes.clone(),
-            update_events,
+            message_revisions,
         )
         .await?;
 
@@ -99,7 +156,14 @@ impl Processable for Storer {
         process_upgrade_module_changes(
             self.pool.clone(),
             per_table_chunk_sizes.clone(),
-            module_upgrades,
+            module_upgrades.clone(),
+        )
+        .await?;
+
+        process_module_abi_changes(
+            self.pool.clone(),
+            per_table_chunk_sizes.clone(),
+            &module_upgrades,
         )
         .await?;
 
@@ -107,9 +171,52 @@ impl Processable for Storer {
             self.pool.clone(),
             per_table_chunk_sizes.clone(),
             package_upgrades,
+            self.webhook_config.clone(),
+        )
+        .await?;
+
+        process_generic_events(
+            self.pool.clone(),
+            per_table_chunk_sizes.clone(),
+            data.generic_events,
+        )
+        .await?;
+
+        process_failed_items(
+            self.pool.clone(),
+            per_table_chunk_sizes.clone(),
+            data.failed_items,
         )
         .await?;
 
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Processable for Storer {
+    type Input = TransactionContextData;
+    type Output = TransactionContextData;
+    type RunType = AsyncRunType;
+
+    async fn process(
+        &mut self,
+        transaction_context_data: TransactionContext<TransactionContextData>,
+    ) -> Result<Option<TransactionContext<TransactionContextData>>, ProcessorError> {
+        if let Some(end_timestamp) = transaction_context_data
+            .metadata
+            .end_transaction_timestamp
+            .as_ref()
+        {
+            self.snapshot_leaderboard_if_due(
+                end_timestamp.seconds,
+                transaction_context_data.metadata.start_version as i64 - 1,
+            )
+            .await?;
+        }
+        self.store(transaction_context_data.data.clone()).await?;
+        BATCHES_PROCESSED_COUNT.inc();
+        observe_db_pool("storer", &self.pool);
         Ok(Some(transaction_context_data))
     }
 }
@@ -3,60 +3,168 @@ use anyhow::Result;
 use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
 use diesel::{insert_into, upsert::excluded, ExpressionMethods, QueryResult};
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
-use std::cmp;
+use std::{cmp, sync::Arc};
 
 use crate::{
-    db_models::{message::Message, user_stat::UserStat},
-    schema::{messages, user_stats},
+    config::{
+        points_config::{PointsConfig, UserPoints},
+        webhook_config::WebhookConfig,
+    },
+    db_models::{
+        event_ledger::EventLedgerEntry,
+        message::Message,
+        outbox::{NotificationKind, OutboxEntry},
+        user_stat::UserStat,
+    },
+    schema::{event_ledger, messages, user_stats},
+    steps::{
+        event_handler::EventHandler,
+        storers::{
+            leaderboard_storer::execute_leaderboard_sql, outbox_storer::execute_outbox_sql,
+            user_season_points_storer::execute_user_season_points_sql,
+        },
+    },
     utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
         database_connection::get_db_connection,
         database_utils::{get_config_table_chunk_size, ArcDbPool},
+        live_updates::LIVE_UPDATES,
     },
 };
 
-const POINT_PER_NEW_MESSAGE: i64 = 2;
-
 async fn execute_create_message_events_sql(
     conn: &mut AsyncPgConnection,
-    items_to_insert: Vec<Message>,
-    user_stats_changes: AHashMap<String, (i64, i64, i64)>,
-) -> QueryResult<()> {
+    items_to_insert: Vec<(Message, EventLedgerEntry)>,
+    points_config: Arc<PointsConfig>,
+    webhook_config: Option<Arc<WebhookConfig>>,
+) -> QueryResult<(Vec<Message>, Vec<UserStat>)> {
     conn.transaction(async move |conn| {
+        let (messages_to_insert, ledger_entries): (Vec<_>, Vec<_>) =
+            items_to_insert.into_iter().unzip();
+
+        // The points each event earns are stored with it, so a rewind takes back exactly the
+        // points awarded even if the rules change later
+        let ledger_entries = ledger_entries
+            .into_iter()
+            .map(|entry| {
+                let season_points = points_config.event_points(
+                    EventHandler::CreateMessage,
+                    entry.txn_version,
+                    entry.event_timestamp,
+                );
+                entry.with_season_points(&season_points)
+            })
+            .collect::<Vec<_>>();
+
+        // Only events that are new to the ledger count towards user stats, so processing
+        // the same event twice (e.g. when backfilling or after a crash) is a no-op
+        let new_ledger_entries = insert_into(event_ledger::table)
+            .values(&ledger_entries)
+            .on_conflict((event_ledger::txn_version, event_ledger::event_index))
+            .do_nothing()
+            .returning((
+                event_ledger::user_addr,
+                event_ledger::event_timestamp,
+                event_ledger::season_points,
+            ))
+            .get_results::<(String, i64, serde_json::Value)>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["event_ledger"])
+            .inc_by(new_ledger_entries.len() as u64);
+
         let create_message_query = insert_into(messages::table)
-            .values(&items_to_insert)
+            .values(&messages_to_insert)
             .on_conflict(messages::message_obj_addr)
-            .do_nothing();
-        create_message_query.execute(conn).await?;
+            .do_nothing()
+            .returning(messages::all_columns);
+        let new_messages = create_message_query.get_results::<Message>(conn).await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["messages"])
+            .inc_by(new_messages.len() as u64);
 
-        /*
-        DO NOT backfill data (i.e. process same event twice), you would mess up the user stat!!!!
-        Instead, if you want to change the point calculation logic, you should delete all data and re-index from scratch.
-        You can delete all data by revert all DB migrations, see README.md for more details.
-         */
-        let update_user_stat_query = insert_into(user_stats::table)
-            .values(
-                user_stats_changes
-                    .iter()
-                    .map(
-                        |(
-                            user_addr,
-                            (
-                                new_message_count,
-                                earliest_message_creation_time,
-                                latest_message_creation_time,
-                            ),
-                        )| UserStat {
-                            user_addr: user_addr.clone(),
-                            creation_timestamp: *earliest_message_creation_time,
-                            last_update_timestamp: *latest_message_creation_time,
-                            created_messages: *new_message_count,
-                            updated_messages: 0,
-                            s1_points: new_message_count * POINT_PER_NEW_MESSAGE,
-                            total_points: new_message_count * POINT_PER_NEW_MESSAGE,
-                        },
-                    )
-                    .collect::<Vec<_>>(),
+        if let Some(webhook_config) = &webhook_config {
+            execute_outbox_sql(
+                conn,
+                OutboxEntry::for_messages(
+                    webhook_config,
+                    NotificationKind::MessageCreated,
+                    &new_messages,
+                ),
             )
+            .await?;
+        }
+
+        if new_ledger_entries.is_empty() {
+            return Ok((new_messages, vec![]));
+        }
+
+        let user_points = UserPoints::from_events(
+            new_ledger_entries
+                .iter()
+                .map(|(user_addr, _, season_points)| {
+                    Ok((
+                        user_addr.clone(),
+                        EventLedgerEntry::parse_season_points(season_points.clone())?,
+                    ))
+                })
+                .collect::<QueryResult<Vec<_>>>()?,
+        );
+
+        // Key is user address
+        // Value is (number of new messages, earliest create message time, latest create message time)
+        let mut user_stats_changes: AHashMap<String, (i64, i64, i64)> = AHashMap::new();
+        for (user_addr, creation_timestamp, _) in &new_ledger_entries {
+            let (new_count, earliest_time, latest_time) = user_stats_changes
+                .get(user_addr)
+                .cloned()
+                .unwrap_or((0, i64::MAX, 0));
+            user_stats_changes.insert(
+                user_addr.clone(),
+                (
+                    new_count + 1,
+                    cmp::min(earliest_time, *creation_timestamp),
+                    cmp::max(latest_time, *creation_timestamp),
+                ),
+            );
+        }
+
+        let mut user_stats_to_upsert = user_stats_changes
+            .into_iter()
+            .map(
+                |(
+                    user_addr,
+                    (
+                        new_message_count,
+                        earliest_message_creation_time,
+                        latest_message_creation_time,
+                    ),
+                )| {
+                    let (total_points, s1_points) = user_points
+                        .totals
+                        .get(&user_addr)
+                        .copied()
+                        .unwrap_or_default();
+                    UserStat {
+                        user_addr,
+                        creation_timestamp: earliest_message_creation_time,
+                        last_update_timestamp: latest_message_creation_time,
+                        created_messages: new_message_count,
+                        updated_messages: 0,
+                        s1_points,
+                        total_points,
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        // Lock rows in the same order in every storer running concurrently
+        user_stats_to_upsert.sort_by(|a, b| a.user_addr.cmp(&b.user_addr));
+        let user_addrs = user_stats_to_upsert
+            .iter()
+            .map(|user_stat| user_stat.user_addr.clone())
+            .collect::<Vec<_>>();
+        let update_user_stat_query = insert_into(user_stats::table)
+            .values(&user_stats_to_upsert)
             .on_conflict(user_stats::user_addr)
             .do_update()
             .set((
@@ -70,9 +178,18 @@ async fn execute_create_message_events_sql(
                 user_stats::total_points
                     .eq(user_stats::total_points + excluded(user_stats::total_points)),
             ));
-        update_user_stat_query.execute(conn).await?;
+        let user_stats = update_user_stat_query
+            .returning(user_stats::all_columns)
+            .get_results::<UserStat>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["user_stats"])
+            .inc_by(user_stats.len() as u64);
+
+        execute_user_season_points_sql(conn, user_points.seasons).await?;
+        execute_leaderboard_sql(conn, user_addrs).await?;
 
-        Ok(())
+        Ok((new_messages, user_stats))
     })
     .await
 }
@@ -80,38 +197,29 @@ async fn execute_create_message_events_sql(
 pub async fn process_create_message_events(
     pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
-    create_events: Vec<Message>,
+    points_config: Arc<PointsConfig>,
+    webhook_config: Option<Arc<WebhookConfig>>,
+    create_events: Vec<(Message, EventLedgerEntry)>,
 ) -> Result<(), ProcessorError> {
-    // Key is user address
-    // Value is (number of new messages, earliest create message time, latest create message time)
-    let mut user_stats_changes: AHashMap<String, (i64, i64, i64)> = AHashMap::new();
-    for message in create_events.clone() {
-        let (new_count, earliest_time, latest_time) = user_stats_changes
-            .get(&message.creator_addr)
-            .cloned()
-            .unwrap_or((0, i64::MAX, 0));
-        user_stats_changes.insert(
-            message.creator_addr.clone(),
-            (
-                new_count + 1,
-                cmp::min(earliest_time, message.creation_timestamp),
-                cmp::max(latest_time, message.creation_timestamp),
-            ),
-        );
-    }
-
     let chunk_size = get_config_table_chunk_size::<Message>("messages", &per_table_chunk_sizes);
     let tasks = create_events
         .chunks(chunk_size)
         .map(|chunk| {
             let pool = pool.clone();
             let items = chunk.to_vec();
-            let user_stats_changes = user_stats_changes.clone();
+            let points_config = points_config.clone();
+            let webhook_config = webhook_config.clone();
             tokio::spawn(async move {
                 let conn = &mut get_db_connection(&pool).await.expect(
                     "Failed to get connection from pool while processing create message events",
                 );
-                execute_create_message_events_sql(conn, items, user_stats_changes).await
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["create_message_events"])
+                    .start_timer();
+                // Published once committed, so the rows can be read back by stream clients
+                execute_create_message_events_sql(conn, items, points_config, webhook_config)
+                    .await
+                    .map(|(messages, user_stats)| LIVE_UPDATES.publish(messages, user_stats))
             })
         })
         .collect::<Vec<_>>();
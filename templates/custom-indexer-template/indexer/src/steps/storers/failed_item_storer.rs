@@ -0,0 +1,76 @@
+use ahash::AHashMap;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use diesel::{insert_into, QueryResult};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::failed_item::FailedItem,
+    schema::failed_items,
+    utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
+        database_connection::get_db_connection,
+        database_utils::{get_config_table_chunk_size, ArcDbPool},
+    },
+};
+
+async fn execute_failed_items_sql(
+    conn: &mut AsyncPgConnection,
+    items_to_insert: Vec<FailedItem>,
+) -> QueryResult<()> {
+    conn.transaction(async move |conn| {
+        // An item that is already dead-lettered keeps its retry count
+        let create_failed_item_query = insert_into(failed_items::table)
+            .values(&items_to_insert)
+            .on_conflict((
+                failed_items::txn_version,
+                failed_items::item_type,
+                failed_items::item_index,
+            ))
+            .do_nothing();
+        let rows = create_failed_item_query.execute(conn).await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["failed_items"])
+            .inc_by(rows as u64);
+        Ok(())
+    })
+    .await
+}
+
+pub async fn process_failed_items(
+    pool: ArcDbPool,
+    per_table_chunk_sizes: AHashMap<String, usize>,
+    failed_items: Vec<FailedItem>,
+) -> Result<(), ProcessorError> {
+    let chunk_size =
+        get_config_table_chunk_size::<FailedItem>("failed_items", &per_table_chunk_sizes);
+    let tasks = failed_items
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let pool = pool.clone();
+            let items = chunk.to_vec();
+            tokio::spawn(async move {
+                let conn = &mut get_db_connection(&pool)
+                    .await
+                    .expect("Failed to get connection from pool while processing failed items");
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["failed_items"])
+                    .start_timer();
+                execute_failed_items_sql(conn, items).await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = futures_util::future::try_join_all(tasks)
+        .await
+        .expect("Task panicked executing in chunks");
+    for res in results {
+        res.map_err(|e| {
+            tracing::warn!("Error running query: {:?}", e);
+            ProcessorError::ProcessError {
+                message: e.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use ahash::AHashMap;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::MoveModule, utils::errors::ProcessorError,
+};
+use diesel::{insert_into, ExpressionMethods, QueryDsl, QueryResult};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::generic_event::GenericEvent,
+    schema::{events, module_upgrade_history},
+    utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
+        database_connection::get_db_connection,
+        database_utils::{get_config_table_chunk_size, ArcDbPool},
+        move_abi::{check_struct_data, parse_module_abi},
+    },
+};
+
+/// Upgrades of a module as (tx_version, upgrade_number, ABI), in version order.
+type ModuleUpgrades = Vec<(i64, i64, Result<MoveModule, String>)>;
+
+async fn load_module_upgrades(
+    conn: &mut AsyncPgConnection,
+    events: &[GenericEvent],
+) -> QueryResult<AHashMap<(String, String), ModuleUpgrades>> {
+    let mut addresses = events
+        .iter()
+        .map(|event| event.account_address.clone())
+        .collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+    let mut module_names = events
+        .iter()
+        .map(|event| event.module_name.clone())
+        .collect::<Vec<_>>();
+    module_names.sort();
+    module_names.dedup();
+    let max_version = events
+        .iter()
+        .map(|event| event.txn_version)
+        .max()
+        .unwrap_or_default();
+
+    let rows = module_upgrade_history::table
+        .filter(module_upgrade_history::module_addr.eq_any(addresses))
+        .filter(module_upgrade_history::module_name.eq_any(module_names))
+        .filter(module_upgrade_history::tx_version.le(max_version))
+        .select((
+            module_upgrade_history::module_addr,
+            module_upgrade_history::module_name,
+            module_upgrade_history::tx_version,
+            module_upgrade_history::upgrade_number,
+            module_upgrade_history::module_abi,
+        ))
+        .order((
+            module_upgrade_history::tx_version.asc(),
+            module_upgrade_history::upgrade_number.asc(),
+        ))
+        .load::<(String, String, i64, i64, serde_json::Value)>(conn)
+        .await?;
+
+    let mut upgrades: AHashMap<(String, String), ModuleUpgrades> = AHashMap::new();
+    for (module_addr, module_name, tx_version, upgrade_number, module_abi) in rows {
+        upgrades
+            .entry((module_addr, module_name))
+            .or_default()
+            .push((
+                tx_version,
+                upgrade_number,
+                parse_module_abi(&module_abi).map_err(|e| format!("{:#}", e)),
+            ));
+    }
+    Ok(upgrades)
+}
+
+/// Sets the upgrade number of the module that emitted each event, i.e. the latest upgrade at
+/// or before the event's version, and checks the event data against that upgrade's ABI.
+fn resolve_upgrades(
+    events: &mut [GenericEvent],
+    upgrades: &AHashMap<(String, String), ModuleUpgrades>,
+) {
+    for event in events {
+        let upgrade = upgrades
+            .get(&(event.account_address.clone(), event.module_name.clone()))
+            .and_then(|module_upgrades| {
+                module_upgrades
+                    .iter()
+                    .rev()
+                    .find(|(tx_version, _, _)| *tx_version <= event.txn_version)
+            });
+        let Some((_, upgrade_number, module)) = upgrade else {
+            event.abi_error.get_or_insert_with(|| {
+                format!(
+                    "No upgrade of module {} recorded at or before version {}",
+                    event.module_name, event.txn_version
+                )
+            });
+            continue;
+        };
+        event.upgrade_number = Some(*upgrade_number);
+        // Keep an earlier error, e.g. data that isn't JSON at all
+        if event.abi_error.is_some() {
+            continue;
+        }
+        event.abi_error = match module {
+            Ok(module) => check_struct_data(module, &event.struct_name, &event.data).err(),
+            Err(e) => Some(e.clone()),
+        };
+    }
+}
+
+async fn execute_generic_events_sql(
+    conn: &mut AsyncPgConnection,
+    items_to_insert: Vec<GenericEvent>,
+) -> QueryResult<()> {
+    conn.transaction(async move |conn| {
+        let create_events_query = insert_into(events::table)
+            .values(&items_to_insert)
+            .on_conflict((events::txn_version, events::event_index))
+            .do_nothing();
+        let rows = create_events_query.execute(conn).await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["events"])
+            .inc_by(rows as u64);
+        Ok(())
+    })
+    .await
+}
+
+/// Stores the generic events of a batch. Must run after the batch's module upgrades are
+/// stored, so events emitted right after an upgrade are checked against the new ABI.
+pub async fn process_generic_events(
+    pool: ArcDbPool,
+    per_table_chunk_sizes: AHashMap<String, usize>,
+    mut generic_events: Vec<GenericEvent>,
+) -> Result<(), ProcessorError> {
+    if generic_events.is_empty() {
+        return Ok(());
+    }
+    let upgrades = {
+        let conn = &mut get_db_connection(&pool).await?;
+        let _timer = STORER_QUERY_LATENCY_SECS
+            .with_label_values(&["load_module_upgrades"])
+            .start_timer();
+        load_module_upgrades(conn, &generic_events)
+            .await
+            .map_err(|e| ProcessorError::ProcessError {
+                message: format!("Failed to load module upgrades: {}", e),
+            })?
+    };
+    resolve_upgrades(&mut generic_events, &upgrades);
+
+    let chunk_size = get_config_table_chunk_size::<GenericEvent>("events", &per_table_chunk_sizes);
+    let tasks = generic_events
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let pool = pool.clone();
+            let items = chunk.to_vec();
+            tokio::spawn(async move {
+                let conn = &mut get_db_connection(&pool)
+                    .await
+                    .expect("Failed to get connection from pool while processing generic events");
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["generic_events"])
+                    .start_timer();
+                execute_generic_events_sql(conn, items).await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = futures_util::future::try_join_all(tasks)
+        .await
+        .expect("Task panicked executing in chunks");
+    for res in results {
+        res.map_err(|e| {
+            tracing::warn!("Error running query: {:?}", e);
+            ProcessorError::ProcessError {
+                message: e.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use diesel::{
+    insert_into, sql_types::BigInt, upsert::excluded, ExpressionMethods, IntoSql,
+    NullableExpressionMethods, QueryDsl, QueryResult,
+};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::leaderboard::LeaderboardEntry,
+    schema::{leaderboard, leaderboard_snapshots, user_stats},
+    utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
+        database_connection::get_db_connection,
+        database_utils::ArcDbPool,
+    },
+};
+
+// Key of the transaction level advisory lock that serializes leaderboard changes. Ranks are
+// derived from the other rows, so concurrent storers must not interleave their updates
+const LEADERBOARD_LOCK_KEY: i64 = 0x6c6561646572;
+
+async fn lock_leaderboard(conn: &mut AsyncPgConnection) -> QueryResult<()> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+        .bind::<BigInt, _>(LEADERBOARD_LOCK_KEY)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Sets the points of users to their current `user_stats.total_points` and ranks the whole
+/// leaderboard again in a single statement. Users without stats are removed from the
+/// leaderboard. This doesn't open a transaction, it's meant to run in the same transaction as
+/// the user_stats upsert of the calling storer.
+///
+/// Ranking sorts every leaderboard row by points under the leaderboard lock, once per storer
+/// transaction, and only writes the rows whose rank changed.
+pub async fn execute_leaderboard_sql(
+    conn: &mut AsyncPgConnection,
+    mut user_addrs: Vec<String>,
+) -> QueryResult<()> {
+    if user_addrs.is_empty() {
+        return Ok(());
+    }
+    user_addrs.sort();
+    user_addrs.dedup();
+    lock_leaderboard(conn).await?;
+
+    let entries = user_stats::table
+        .filter(user_stats::user_addr.eq_any(&user_addrs))
+        .order(user_stats::user_addr)
+        .select((user_stats::user_addr, user_stats::total_points))
+        .load::<(String, i64)>(conn)
+        .await?
+        .into_iter()
+        .map(|(user_addr, points)| LeaderboardEntry {
+            user_addr,
+            points,
+            // Set by the ranking below
+            rank: 0,
+            previous_rank: None,
+            rank_delta: None,
+        })
+        .collect::<Vec<_>>();
+    let removed_user_addrs = user_addrs
+        .iter()
+        .filter(|user_addr| !entries.iter().any(|entry| &entry.user_addr == *user_addr))
+        .collect::<Vec<_>>();
+
+    let mut rows = 0;
+    if !removed_user_addrs.is_empty() {
+        rows += diesel::delete(
+            leaderboard::table.filter(leaderboard::user_addr.eq_any(removed_user_addrs)),
+        )
+        .execute(conn)
+        .await?;
+    }
+    if !entries.is_empty() {
+        rows += insert_into(leaderboard::table)
+            .values(&entries)
+            .on_conflict(leaderboard::user_addr)
+            .do_update()
+            .set(leaderboard::points.eq(excluded(leaderboard::points)))
+            .filter(leaderboard::points.ne(excluded(leaderboard::points)))
+            .execute(conn)
+            .await?;
+    }
+    if rows == 0 {
+        return Ok(());
+    }
+
+    // Users with the same points share a rank, rank_delta stays null until the user is in a
+    // snapshot
+    rows += diesel::sql_query(
+        "UPDATE leaderboard \
+        SET rank = ranked.rank, rank_delta = leaderboard.previous_rank - ranked.rank \
+        FROM ( \
+            SELECT user_addr, RANK() OVER (ORDER BY points DESC) AS rank FROM leaderboard \
+        ) ranked \
+        WHERE ranked.user_addr = leaderboard.user_addr \
+        AND ranked.rank <> leaderboard.rank",
+    )
+    .execute(conn)
+    .await?;
+    ROWS_WRITTEN_COUNT
+        .with_label_values(&["leaderboard"])
+        .inc_by(rows as u64);
+    Ok(())
+}
+
+async fn execute_leaderboard_snapshot_sql(
+    conn: &mut AsyncPgConnection,
+    snapshot_timestamp: i64,
+    txn_version: i64,
+) -> QueryResult<()> {
+    conn.transaction(async move |conn| {
+        lock_leaderboard(conn).await?;
+
+        // A snapshot that already exists, e.g. when a batch is replayed, is kept as is
+        let rows = insert_into(leaderboard_snapshots::table)
+            .values(leaderboard::table.select((
+                snapshot_timestamp.into_sql::<BigInt>(),
+                leaderboard::user_addr,
+                leaderboard::rank,
+                leaderboard::points,
+                txn_version.into_sql::<BigInt>(),
+            )))
+            .into_columns((
+                leaderboard_snapshots::snapshot_timestamp,
+                leaderboard_snapshots::user_addr,
+                leaderboard_snapshots::rank,
+                leaderboard_snapshots::points,
+                leaderboard_snapshots::txn_version,
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["leaderboard_snapshots"])
+            .inc_by(rows as u64);
+
+        // Rank deltas are relative to the latest snapshot
+        diesel::update(leaderboard::table)
+            .set((
+                leaderboard::previous_rank.eq(leaderboard::rank.nullable()),
+                leaderboard::rank_delta.eq(Some(0)),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Points previous_rank and rank_delta back at the latest snapshot left, after later snapshots
+/// were removed. This doesn't open a transaction, it's meant to run in the rewind's.
+pub async fn execute_leaderboard_rebase_sql(conn: &mut AsyncPgConnection) -> QueryResult<()> {
+    lock_leaderboard(conn).await?;
+    diesel::update(leaderboard::table)
+        .set((
+            leaderboard::previous_rank.eq(None::<i64>),
+            leaderboard::rank_delta.eq(None::<i64>),
+        ))
+        .execute(conn)
+        .await?;
+    diesel::sql_query(
+        "UPDATE leaderboard \
+        SET previous_rank = s.rank, rank_delta = s.rank - leaderboard.rank \
+        FROM leaderboard_snapshots s \
+        WHERE s.user_addr = leaderboard.user_addr \
+        AND s.snapshot_timestamp = (SELECT MAX(snapshot_timestamp) FROM leaderboard_snapshots)",
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Copies the current leaderboard into leaderboard_snapshots under `snapshot_timestamp`, along
+/// with the last transaction version the ranks include.
+pub async fn process_leaderboard_snapshot(
+    pool: ArcDbPool,
+    snapshot_timestamp: i64,
+    txn_version: i64,
+) -> Result<(), ProcessorError> {
+    let conn = &mut get_db_connection(&pool).await?;
+    let _timer = STORER_QUERY_LATENCY_SECS
+        .with_label_values(&["leaderboard_snapshot"])
+        .start_timer();
+    execute_leaderboard_snapshot_sql(conn, snapshot_timestamp, txn_version)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Error running query: {:?}", e);
+            ProcessorError::ProcessError {
+                message: e.to_string(),
+            }
+        })
+}
+
+/// Timestamp of the latest leaderboard snapshot, None if none was taken yet.
+pub async fn get_latest_snapshot_timestamp(
+    pool: &ArcDbPool,
+) -> Result<Option<i64>, ProcessorError> {
+    let conn = &mut get_db_connection(pool).await?;
+    leaderboard_snapshots::table
+        .select(diesel::dsl::max(leaderboard_snapshots::snapshot_timestamp))
+        .first::<Option<i64>>(conn)
+        .await
+        .map_err(|e| ProcessorError::ProcessError {
+            message: e.to_string(),
+        })
+}
@@ -0,0 +1,99 @@
+use ahash::AHashMap;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use diesel::{
+    insert_into,
+    sql_types::{Array, Text},
+    QueryResult,
+};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::message_revision::MessageRevision,
+    schema::message_revisions,
+    utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
+        database_connection::get_db_connection,
+        database_utils::{get_config_table_chunk_size, ArcDbPool},
+    },
+};
+
+async fn execute_message_revisions_sql(
+    conn: &mut AsyncPgConnection,
+    items_to_insert: Vec<MessageRevision>,
+) -> QueryResult<()> {
+    conn.transaction(async move |conn| {
+        // Revisions that are already indexed, e.g. when a batch is replayed, are not written
+        // again
+        let mut message_obj_addrs = insert_into(message_revisions::table)
+            .values(&items_to_insert)
+            .on_conflict((
+                message_revisions::txn_version,
+                message_revisions::event_index,
+            ))
+            .do_nothing()
+            .returning(message_revisions::message_obj_addr)
+            .get_results::<String>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["message_revisions"])
+            .inc_by(message_obj_addrs.len() as u64);
+        if message_obj_addrs.is_empty() {
+            return Ok(());
+        }
+        message_obj_addrs.sort();
+        message_obj_addrs.dedup();
+
+        // Number the revisions of each message in (txn_version, event_index) order, so a
+        // revision stored after later ones, e.g. when it's re-driven, shifts them by one. The
+        // unique revision numbers are only checked on commit
+        diesel::sql_query(
+            "UPDATE message_revisions \
+            SET revision_number = numbered.revision_number \
+            FROM ( \
+                SELECT txn_version, event_index, ROW_NUMBER() OVER ( \
+                    PARTITION BY message_obj_addr ORDER BY txn_version, event_index \
+                ) AS revision_number \
+                FROM message_revisions \
+                WHERE message_obj_addr = ANY($1) \
+            ) numbered \
+            WHERE message_revisions.txn_version = numbered.txn_version \
+            AND message_revisions.event_index = numbered.event_index \
+            AND message_revisions.revision_number <> numbered.revision_number",
+        )
+        .bind::<Array<Text>, _>(message_obj_addrs)
+        .execute(conn)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+/// Stores the revisions of a batch in (txn_version, event_index) order. Unlike the other
+/// storers, chunks run one after the other because each one renumbers the revisions of the
+/// messages it stores.
+pub async fn process_message_revisions(
+    pool: ArcDbPool,
+    per_table_chunk_sizes: AHashMap<String, usize>,
+    mut revisions: Vec<MessageRevision>,
+) -> Result<(), ProcessorError> {
+    revisions.sort_by_key(|revision| (revision.txn_version, revision.event_index));
+
+    let chunk_size =
+        get_config_table_chunk_size::<MessageRevision>("message_revisions", &per_table_chunk_sizes);
+    for chunk in revisions.chunks(chunk_size) {
+        let conn = &mut get_db_connection(&pool).await?;
+        let _timer = STORER_QUERY_LATENCY_SECS
+            .with_label_values(&["message_revisions"])
+            .start_timer();
+        execute_message_revisions_sql(conn, chunk.to_vec())
+            .await
+            .map_err(|e| {
+                tracing::warn!("Error running query: {:?}", e);
+                ProcessorError::ProcessError {
+                    message: e.to_string(),
+                }
+            })?;
+    }
+    Ok(())
+}
@@ -1,4 +1,11 @@
 pub mod create_message_event_storer;
+pub mod failed_item_storer;
+pub mod generic_event_storer;
+pub mod leaderboard_storer;
+pub mod message_revision_storer;
+pub mod module_abi_change_storer;
+pub mod outbox_storer;
 pub mod update_message_event_storer;
 pub mod upgrade_module_change_storer;
 pub mod upgrade_package_change_storer;
+pub mod user_season_points_storer;
@@ -0,0 +1,161 @@
+use ahash::AHashMap;
+use anyhow::Result;
+use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
+use diesel::{insert_into, ExpressionMethods, QueryDsl, QueryResult};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::{module_abi_change::ModuleAbiChange, module_upgrade::ModuleUpgrade},
+    schema::{module_abi_changes, module_upgrade_history},
+    utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
+        database_connection::get_db_connection,
+        database_utils::{get_config_table_chunk_size, ArcDbPool},
+        move_abi::{diff_module_abis, parse_module_abi},
+    },
+};
+
+/// Diffs each upgrade against the previous upgrade of the same module. The first upgrade
+/// indexed of a module has nothing to be diffed against.
+async fn load_abi_changes(
+    conn: &mut AsyncPgConnection,
+    module_upgrades: &[ModuleUpgrade],
+) -> Result<Vec<ModuleAbiChange>> {
+    let module_addrs = module_upgrades
+        .iter()
+        .map(|upgrade| upgrade.module_addr.clone())
+        .collect::<Vec<_>>();
+    let module_names = module_upgrades
+        .iter()
+        .map(|upgrade| upgrade.module_name.clone())
+        .collect::<Vec<_>>();
+    let mut previous_abis: AHashMap<(String, String), Vec<(i64, serde_json::Value)>> =
+        AHashMap::new();
+    // Upgrades are rare, loading every ABI of the upgraded modules stays cheap
+    for (module_addr, module_name, upgrade_number, module_abi) in module_upgrade_history::table
+        .filter(module_upgrade_history::module_addr.eq_any(module_addrs))
+        .filter(module_upgrade_history::module_name.eq_any(module_names))
+        .select((
+            module_upgrade_history::module_addr,
+            module_upgrade_history::module_name,
+            module_upgrade_history::upgrade_number,
+            module_upgrade_history::module_abi,
+        ))
+        .load::<(String, String, i64, serde_json::Value)>(conn)
+        .await?
+    {
+        previous_abis
+            .entry((module_addr, module_name))
+            .or_default()
+            .push((upgrade_number, module_abi));
+    }
+
+    let mut abi_changes = vec![];
+    for upgrade in module_upgrades {
+        let Some((previous_upgrade_number, previous_abi)) = previous_abis
+            .get(&(upgrade.module_addr.clone(), upgrade.module_name.clone()))
+            .and_then(|abis| {
+                abis.iter()
+                    .filter(|(upgrade_number, _)| *upgrade_number < upgrade.upgrade_number)
+                    .max_by_key(|(upgrade_number, _)| *upgrade_number)
+            })
+        else {
+            continue;
+        };
+        let diff = diff_module_abis(
+            &parse_module_abi(previous_abi)?,
+            &parse_module_abi(&upgrade.module_abi)?,
+        );
+        abi_changes.extend(diff.into_iter().enumerate().map(|(change_index, change)| {
+            ModuleAbiChange::from_abi_change(
+                &upgrade.module_addr,
+                &upgrade.module_name,
+                (*previous_upgrade_number, upgrade.upgrade_number),
+                change_index,
+                change,
+                upgrade.tx_version,
+            )
+        }));
+    }
+    Ok(abi_changes)
+}
+
+async fn execute_module_abi_changes_sql(
+    conn: &mut AsyncPgConnection,
+    items_to_insert: Vec<ModuleAbiChange>,
+) -> QueryResult<()> {
+    conn.transaction(async move |conn| {
+        let create_abi_changes_query = insert_into(module_abi_changes::table)
+            .values(&items_to_insert)
+            .on_conflict((
+                module_abi_changes::module_addr,
+                module_abi_changes::module_name,
+                module_abi_changes::upgrade_number,
+                module_abi_changes::change_index,
+            ))
+            .do_nothing();
+        let rows = create_abi_changes_query.execute(conn).await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["module_abi_changes"])
+            .inc_by(rows as u64);
+        Ok(())
+    })
+    .await
+}
+
+/// Stores the ABI changes of a batch's module upgrades. Must run after the upgrades are
+/// stored, so upgrades of the same module within the batch are diffed against each other.
+pub async fn process_module_abi_changes(
+    pool: ArcDbPool,
+    per_table_chunk_sizes: AHashMap<String, usize>,
+    module_upgrades: &[ModuleUpgrade],
+) -> Result<(), ProcessorError> {
+    if module_upgrades.is_empty() {
+        return Ok(());
+    }
+    let abi_changes = {
+        let conn = &mut get_db_connection(&pool).await?;
+        let _timer = STORER_QUERY_LATENCY_SECS
+            .with_label_values(&["load_abi_changes"])
+            .start_timer();
+        load_abi_changes(conn, module_upgrades)
+            .await
+            .map_err(|e| ProcessorError::ProcessError {
+                message: format!("Failed to diff module ABIs: {:#}", e),
+            })?
+    };
+
+    let chunk_size = get_config_table_chunk_size::<ModuleAbiChange>(
+        "module_abi_changes",
+        &per_table_chunk_sizes,
+    );
+    let tasks = abi_changes
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let pool = pool.clone();
+            let items = chunk.to_vec();
+            tokio::spawn(async move {
+                let conn = &mut get_db_connection(&pool).await.expect(
+                    "Failed to get connection from pool while processing module ABI changes",
+                );
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["module_abi_changes"])
+                    .start_timer();
+                execute_module_abi_changes_sql(conn, items).await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = futures_util::future::try_join_all(tasks)
+        .await
+        .expect("Task panicked executing in chunks");
+    for res in results {
+        res.map_err(|e| {
+            tracing::warn!("Error running query: {:?}", e);
+            ProcessorError::ProcessError {
+                message: e.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use diesel::{insert_into, QueryResult};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+use crate::{db_models::outbox::OutboxEntry, schema::outbox, utils::counters::ROWS_WRITTEN_COUNT};
+
+/// Queues webhook notifications for the delivery worker. This doesn't open a transaction, it's
+/// meant to run in the same transaction as the change notified, so a change is committed along
+/// with its notifications or not at all.
+pub async fn execute_outbox_sql(
+    conn: &mut AsyncPgConnection,
+    items_to_insert: Vec<OutboxEntry>,
+) -> QueryResult<()> {
+    if items_to_insert.is_empty() {
+        return Ok(());
+    }
+    let rows = insert_into(outbox::table)
+        .values(&items_to_insert)
+        .on_conflict((outbox::endpoint, outbox::notification_key))
+        .do_nothing()
+        .execute(conn)
+        .await?;
+    ROWS_WRITTEN_COUNT
+        .with_label_values(&["outbox"])
+        .inc_by(rows as u64);
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::cmp;
+use std::{cmp, sync::Arc};
 
 use ahash::AHashMap;
 use anyhow::Result;
@@ -10,22 +10,93 @@ use diesel::{
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 
 use crate::{
-    db_models::{message::Message, user_stat::UserStat},
-    schema::{messages, user_stats},
+    config::{
+        points_config::{PointsConfig, UserPoints},
+        webhook_config::WebhookConfig,
+    },
+    db_models::{
+        event_ledger::EventLedgerEntry,
+        message::Message,
+        outbox::{NotificationKind, OutboxEntry},
+        user_stat::UserStat,
+    },
+    schema::{event_ledger, messages, user_stats},
+    steps::{
+        event_handler::EventHandler,
+        storers::{
+            leaderboard_storer::execute_leaderboard_sql, outbox_storer::execute_outbox_sql,
+            user_season_points_storer::execute_user_season_points_sql,
+        },
+    },
     utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
         database_connection::get_db_connection,
         database_utils::{get_config_table_chunk_size, ArcDbPool},
+        live_updates::LIVE_UPDATES,
     },
 };
 
-const POINT_PER_UPDATE_MESSAGE: i64 = 1;
-
 async fn execute_update_message_events_sql(
     conn: &mut AsyncPgConnection,
-    items_to_insert: Vec<Message>,
-    user_stats_changes: AHashMap<String, (i64, i64)>,
-) -> QueryResult<()> {
+    items_to_insert: Vec<(Message, EventLedgerEntry)>,
+    points_config: Arc<PointsConfig>,
+    webhook_config: Option<Arc<WebhookConfig>>,
+) -> QueryResult<(Vec<Message>, Vec<UserStat>)> {
     conn.transaction(async move |conn| {
+        let (update_events, ledger_entries): (Vec<_>, Vec<_>) = items_to_insert.into_iter().unzip();
+
+        // The points each event earns are stored with it, so a rewind takes back exactly the
+        // points awarded even if the rules change later
+        let ledger_entries = ledger_entries
+            .into_iter()
+            .map(|entry| {
+                let season_points = points_config.event_points(
+                    EventHandler::UpdateMessage,
+                    entry.txn_version,
+                    entry.event_timestamp,
+                );
+                entry.with_season_points(&season_points)
+            })
+            .collect::<Vec<_>>();
+
+        // Only events that are new to the ledger count towards user stats, so processing
+        // the same event twice (e.g. when backfilling or after a crash) is a no-op
+        let new_ledger_entries = insert_into(event_ledger::table)
+            .values(&ledger_entries)
+            .on_conflict((event_ledger::txn_version, event_ledger::event_index))
+            .do_nothing()
+            .returning((
+                event_ledger::user_addr,
+                event_ledger::event_timestamp,
+                event_ledger::season_points,
+            ))
+            .get_results::<(String, i64, serde_json::Value)>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["event_ledger"])
+            .inc_by(new_ledger_entries.len() as u64);
+
+        // Filter update_events so when there are 2 events updating the same record, only the latest one is sent to DB for update
+        // because we cannot update one record with 2 different values in the same statement
+        let mut filtered_update_events_map: AHashMap<String, Message> = AHashMap::new();
+        for message in update_events {
+            filtered_update_events_map
+                .entry(message.message_obj_addr.clone())
+                .and_modify(|existing| {
+                    if (
+                        message.last_update_txn_version,
+                        message.last_update_event_idx,
+                    ) > (
+                        existing.last_update_txn_version,
+                        existing.last_update_event_idx,
+                    ) {
+                        *existing = message.clone();
+                    }
+                })
+                .or_insert(message);
+        }
+        let items_to_insert: Vec<Message> = filtered_update_events_map.into_values().collect();
+
         let update_message_query = insert_into(messages::table)
             .values(&items_to_insert)
             .on_conflict(messages::message_obj_addr)
@@ -37,47 +108,104 @@ async fn execute_update_message_events_sql(
                 messages::last_update_timestamp.eq(excluded(messages::last_update_timestamp)),
                 messages::last_update_event_idx.eq(excluded(messages::last_update_event_idx)),
                 messages::content.eq(excluded(messages::content)),
+                messages::contract_addr.eq(messages::contract_addr),
+                messages::last_update_txn_version.eq(excluded(messages::last_update_txn_version)),
             ))
             .filter(
-                // Update only if the last update timestamp is greater than the existing one
-                // or if the last update timestamp is the same but the event index is greater
-                messages::last_update_timestamp
-                    .lt(excluded(messages::last_update_timestamp))
-                    .or(messages::last_update_timestamp
-                        .eq(excluded(messages::last_update_timestamp))
+                // Update only if the event comes from a later transaction than the existing one
+                // or if it's from the same transaction but the event index is greater
+                messages::last_update_txn_version
+                    .lt(excluded(messages::last_update_txn_version))
+                    .or(messages::last_update_txn_version
+                        .eq(excluded(messages::last_update_txn_version))
                         .and(
                             messages::last_update_event_idx
                                 .lt(excluded(messages::last_update_event_idx)),
                         )),
             );
-        update_message_query.execute(conn).await?;
+        let updated_messages = update_message_query
+            .returning(messages::all_columns)
+            .get_results::<Message>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["messages"])
+            .inc_by(updated_messages.len() as u64);
 
-        /*
-        DO NOT backfill data (i.e. process same event twice), you would mess up the user stat!!!!
-        Instead, if you want to change the point calculation logic, you should delete all data and re-index from scratch.
-        You can delete all data by revert all DB migrations, see README.md for more details.
-         */
-        let update_user_stat_query = insert_into(user_stats::table)
-            .values(
-                user_stats_changes
-                    .iter()
-                    .map(
-                        |(user_addr, (update_message_count, latest_message_update_time))| {
-                            UserStat {
-                                user_addr: user_addr.clone(),
-                                // This value doesn't matter because we always use the original DB value for creation_timestamp
-                                creation_timestamp: 0,
-                                last_update_timestamp: *latest_message_update_time,
-                                // This value doesn't matter because we always use the original DB value for created_messages
-                                created_messages: 0,
-                                updated_messages: *update_message_count,
-                                s1_points: update_message_count * POINT_PER_UPDATE_MESSAGE,
-                                total_points: update_message_count * POINT_PER_UPDATE_MESSAGE,
-                            }
-                        },
-                    )
-                    .collect::<Vec<_>>(),
+        // Updates older than the stored message aren't written, so they aren't notified
+        if let Some(webhook_config) = &webhook_config {
+            execute_outbox_sql(
+                conn,
+                OutboxEntry::for_messages(
+                    webhook_config,
+                    NotificationKind::MessageUpdated,
+                    &updated_messages,
+                ),
             )
+            .await?;
+        }
+
+        if new_ledger_entries.is_empty() {
+            return Ok((updated_messages, vec![]));
+        }
+
+        let user_points = UserPoints::from_events(
+            new_ledger_entries
+                .iter()
+                .map(|(user_addr, _, season_points)| {
+                    Ok((
+                        user_addr.clone(),
+                        EventLedgerEntry::parse_season_points(season_points.clone())?,
+                    ))
+                })
+                .collect::<QueryResult<Vec<_>>>()?,
+        );
+
+        // Key is user address
+        // Value is (number of updated messages, latest update message time)
+        let mut user_stats_changes: AHashMap<String, (i64, i64)> = AHashMap::new();
+        for (user_addr, last_update_timestamp, _) in &new_ledger_entries {
+            let (update_count, latest_time) =
+                user_stats_changes.get(user_addr).cloned().unwrap_or((0, 0));
+            user_stats_changes.insert(
+                user_addr.clone(),
+                (
+                    update_count + 1,
+                    cmp::max(latest_time, *last_update_timestamp),
+                ),
+            );
+        }
+
+        let mut user_stats_to_upsert = user_stats_changes
+            .into_iter()
+            .map(
+                |(user_addr, (update_message_count, latest_message_update_time))| {
+                    let (total_points, s1_points) = user_points
+                        .totals
+                        .get(&user_addr)
+                        .copied()
+                        .unwrap_or_default();
+                    UserStat {
+                        user_addr,
+                        // This value doesn't matter because we always use the original DB value for creation_timestamp
+                        creation_timestamp: 0,
+                        last_update_timestamp: latest_message_update_time,
+                        // This value doesn't matter because we always use the original DB value for created_messages
+                        created_messages: 0,
+                        updated_messages: update_message_count,
+                        s1_points,
+                        total_points,
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        // Lock rows in the same order in every storer running concurrently
+        user_stats_to_upsert.sort_by(|a, b| a.user_addr.cmp(&b.user_addr));
+        let user_addrs = user_stats_to_upsert
+            .iter()
+            .map(|user_stat| user_stat.user_addr.clone())
+            .collect::<Vec<_>>();
+        let update_user_stat_query = insert_into(user_stats::table)
+            .values(&user_stats_to_upsert)
             .on_conflict(user_stats::user_addr)
             .do_update()
             .set((
@@ -91,9 +219,18 @@ async fn execute_update_message_events_sql(
                 user_stats::total_points
                     .eq(user_stats::total_points + excluded(user_stats::total_points)),
             ));
-        update_user_stat_query.execute(conn).await?;
+        let user_stats = update_user_stat_query
+            .returning(user_stats::all_columns)
+            .get_results::<UserStat>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["user_stats"])
+            .inc_by(user_stats.len() as u64);
+
+        execute_user_season_points_sql(conn, user_points.seasons).await?;
+        execute_leaderboard_sql(conn, user_addrs).await?;
 
-        Ok(())
+        Ok((updated_messages, user_stats))
     })
     .await
 }
@@ -101,57 +238,29 @@ async fn execute_update_message_events_sql(
 pub async fn process_update_message_events(
     pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
-    update_events: Vec<Message>,
+    points_config: Arc<PointsConfig>,
+    webhook_config: Option<Arc<WebhookConfig>>,
+    update_events: Vec<(Message, EventLedgerEntry)>,
 ) -> Result<(), ProcessorError> {
-    // Key is user address
-    // Value is (number of updated messages, latest update message time)
-    let mut user_stats_changes: AHashMap<String, (i64, i64)> = AHashMap::new();
-    for message in update_events.clone() {
-        let (update_count, latest_time) = user_stats_changes
-            .get(&message.creator_addr)
-            .cloned()
-            .unwrap_or((0, 0));
-        user_stats_changes.insert(
-            message.creator_addr.clone(),
-            (
-                update_count + 1,
-                cmp::max(latest_time, message.last_update_timestamp),
-            ),
-        );
-    }
-
-    // Filter update_events so when there are 2 events updating the same record, only the latest one is sent to DB for update
-    // because we cannot update one record with 2 different values in the same transaction
-    let mut filtered_update_events_map: AHashMap<String, Message> = AHashMap::new();
-    for message in update_events {
-        filtered_update_events_map
-            .entry(message.message_obj_addr.clone())
-            .and_modify(|existing| {
-                if (message.last_update_timestamp, message.last_update_event_idx)
-                    > (
-                        existing.last_update_timestamp,
-                        existing.last_update_event_idx,
-                    )
-                {
-                    *existing = message.clone();
-                }
-            })
-            .or_insert(message);
-    }
-    let filtered_update_events: Vec<Message> = filtered_update_events_map.into_values().collect();
-
     let chunk_size = get_config_table_chunk_size::<Message>("messages", &per_table_chunk_sizes);
-    let tasks = filtered_update_events
+    let tasks = update_events
         .chunks(chunk_size)
         .map(|chunk| {
             let pool = pool.clone();
             let items = chunk.to_vec();
-            let user_stats_changes = user_stats_changes.clone();
+            let points_config = points_config.clone();
+            let webhook_config = webhook_config.clone();
             tokio::spawn(async move {
                 let conn = &mut get_db_connection(&pool).await.expect(
                     "Failed to get connection from pool while processing update message events",
                 );
-                execute_update_message_events_sql(conn, items, user_stats_changes).await
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["update_message_events"])
+                    .start_timer();
+                // Published once committed, so the rows can be read back by stream clients
+                execute_update_message_events_sql(conn, items, points_config, webhook_config)
+                    .await
+                    .map(|(messages, user_stats)| LIVE_UPDATES.publish(messages, user_stats))
             })
         })
         .collect::<Vec<_>>();
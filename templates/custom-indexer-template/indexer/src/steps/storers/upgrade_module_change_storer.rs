@@ -8,6 +8,7 @@ use crate::{
     db_models::module_upgrade::ModuleUpgrade,
     schema::module_upgrade_history,
     utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
         database_connection::get_db_connection,
         database_utils::{get_config_table_chunk_size, ArcDbPool},
     },
@@ -26,7 +27,10 @@ async fn execute_upgrade_module_changes_sql(
                 module_upgrade_history::upgrade_number,
             ))
             .do_nothing();
-        create_module_upgrade_query.execute(conn).await?;
+        let rows = create_module_upgrade_query.execute(conn).await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["module_upgrade_history"])
+            .inc_by(rows as u64);
         Ok(())
     })
     .await
@@ -50,6 +54,9 @@ pub async fn process_upgrade_module_changes(
                 let conn = &mut get_db_connection(&pool).await.expect(
                     "Failed to get connection from pool while processing upgrade module changes",
                 );
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["upgrade_module_changes"])
+                    .start_timer();
                 execute_upgrade_module_changes_sql(conn, items).await
             })
         })
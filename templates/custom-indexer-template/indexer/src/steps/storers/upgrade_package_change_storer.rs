@@ -1,13 +1,20 @@
 use ahash::AHashMap;
 use anyhow::Result;
 use aptos_indexer_processor_sdk::utils::errors::ProcessorError;
-use diesel::{insert_into, QueryResult};
+use diesel::{insert_into, QueryDsl, QueryResult};
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
 
 use crate::{
-    db_models::package_upgrade::PackageUpgrade,
+    config::webhook_config::WebhookConfig,
+    db_models::{
+        outbox::{NotificationKind, OutboxEntry},
+        package_upgrade::PackageUpgrade,
+    },
     schema::package_upgrade_history,
+    steps::storers::outbox_storer::execute_outbox_sql,
     utils::{
+        counters::{ROWS_WRITTEN_COUNT, STORER_QUERY_LATENCY_SECS},
         database_connection::get_db_connection,
         database_utils::{get_config_table_chunk_size, ArcDbPool},
     },
@@ -16,6 +23,7 @@ use crate::{
 async fn execute_upgrade_package_changes_sql(
     conn: &mut AsyncPgConnection,
     items_to_insert: Vec<PackageUpgrade>,
+    webhook_config: Option<Arc<WebhookConfig>>,
 ) -> QueryResult<()> {
     conn.transaction(async move |conn| {
         let create_package_upgrade_query = insert_into(package_upgrade_history::table)
@@ -25,8 +33,47 @@ async fn execute_upgrade_package_changes_sql(
                 package_upgrade_history::package_name,
                 package_upgrade_history::upgrade_number,
             ))
-            .do_nothing();
-        create_package_upgrade_query.execute(conn).await?;
+            .do_nothing()
+            .returning((
+                package_upgrade_history::package_addr,
+                package_upgrade_history::package_name,
+                package_upgrade_history::upgrade_number,
+                package_upgrade_history::upgrade_policy,
+                package_upgrade_history::tx_version,
+            ));
+        let new_package_upgrades = create_package_upgrade_query
+            .get_results::<(String, String, i64, i64, i64)>(conn)
+            .await?;
+        ROWS_WRITTEN_COUNT
+            .with_label_values(&["package_upgrade_history"])
+            .inc_by(new_package_upgrades.len() as u64);
+
+        // Only upgrades that are new to the table are notified
+        if let Some(webhook_config) = webhook_config {
+            let outbox_entries = new_package_upgrades
+                .into_iter()
+                .flat_map(
+                    |(package_addr, package_name, upgrade_number, upgrade_policy, tx_version)| {
+                        OutboxEntry::for_subscribers(
+                            &webhook_config,
+                            NotificationKind::PackageUpgraded,
+                            format!(
+                                "package_upgraded:{}::{}:{}",
+                                package_addr, package_name, upgrade_number
+                            ),
+                            tx_version,
+                            serde_json::json!({
+                                "package_addr": package_addr,
+                                "package_name": package_name,
+                                "upgrade_number": upgrade_number,
+                                "upgrade_policy": upgrade_policy,
+                            }),
+                        )
+                    },
+                )
+                .collect();
+            execute_outbox_sql(conn, outbox_entries).await?;
+        }
         Ok(())
     })
     .await
@@ -36,6 +83,7 @@ pub async fn process_upgrade_package_changes(
     pool: ArcDbPool,
     per_table_chunk_sizes: AHashMap<String, usize>,
     upgrade_changes: Vec<PackageUpgrade>,
+    webhook_config: Option<Arc<WebhookConfig>>,
 ) -> Result<(), ProcessorError> {
     let chunk_size = get_config_table_chunk_size::<PackageUpgrade>(
         "package_upgrade_history",
@@ -46,11 +94,15 @@ pub async fn process_upgrade_package_changes(
         .map(|chunk| {
             let pool = pool.clone();
             let items = chunk.to_vec();
+            let webhook_config = webhook_config.clone();
             tokio::spawn(async move {
                 let conn = &mut get_db_connection(&pool).await.expect(
                     "Failed to get connection from pool while processing upgrade package changes",
                 );
-                execute_upgrade_package_changes_sql(conn, items).await
+                let _timer = STORER_QUERY_LATENCY_SECS
+                    .with_label_values(&["upgrade_package_changes"])
+                    .start_timer();
+                execute_upgrade_package_changes_sql(conn, items, webhook_config).await
             })
         })
         .collect::<Vec<_>>();
@@ -68,3 +120,31 @@ pub async fn process_upgrade_package_changes(
     }
     Ok(())
 }
+
+/// Latest stored upgrade_number of each package, keyed by (address, package name).
+pub async fn get_package_upgrade_numbers(
+    pool: &ArcDbPool,
+) -> Result<AHashMap<(String, String), i64>, ProcessorError> {
+    let conn = &mut get_db_connection(pool).await?;
+    let rows = package_upgrade_history::table
+        .group_by((
+            package_upgrade_history::package_addr,
+            package_upgrade_history::package_name,
+        ))
+        .select((
+            package_upgrade_history::package_addr,
+            package_upgrade_history::package_name,
+            diesel::dsl::max(package_upgrade_history::upgrade_number),
+        ))
+        .load::<(String, String, Option<i64>)>(conn)
+        .await
+        .map_err(|e| ProcessorError::ProcessError {
+            message: e.to_string(),
+        })?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(package_addr, package_name, upgrade_number)| {
+            Some(((package_addr, package_name), upgrade_number?))
+        })
+        .collect())
+}
@@ -0,0 +1,43 @@
+use ahash::AHashMap;
+use diesel::{insert_into, upsert::excluded, ExpressionMethods, QueryResult};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+use crate::{
+    db_models::user_season_points::UserSeasonPoints, schema::user_season_points,
+    utils::counters::ROWS_WRITTEN_COUNT,
+};
+
+/// Adds points to the season totals of users. This doesn't open a transaction, it's meant to
+/// run in the same transaction as the user_stats upsert of the calling storer.
+pub async fn execute_user_season_points_sql(
+    conn: &mut AsyncPgConnection,
+    season_points: AHashMap<(String, String), i64>,
+) -> QueryResult<()> {
+    let mut items_to_insert = season_points
+        .into_iter()
+        .map(|((user_addr, season), points)| UserSeasonPoints {
+            user_addr,
+            season,
+            points,
+        })
+        .collect::<Vec<_>>();
+    if items_to_insert.is_empty() {
+        return Ok(());
+    }
+    // Lock rows in the same order in every storer running concurrently
+    items_to_insert.sort_by(|a, b| (&a.user_addr, &a.season).cmp(&(&b.user_addr, &b.season)));
+
+    let update_season_points_query = insert_into(user_season_points::table)
+        .values(&items_to_insert)
+        .on_conflict((user_season_points::user_addr, user_season_points::season))
+        .do_update()
+        .set(
+            user_season_points::points
+                .eq(user_season_points::points + excluded(user_season_points::points)),
+        );
+    let rows = update_season_points_query.execute(conn).await?;
+    ROWS_WRITTEN_COUNT
+        .with_label_values(&["user_season_points"])
+        .inc_by(rows as u64);
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Gauge, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec,
+};
+
+use super::database_utils::ArcDbPool;
+
+/// Last version whose processor status has been saved to the DB
+pub static LAST_PROCESSED_VERSION: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "indexer_last_processed_version",
+        "Last transaction version successfully processed"
+    )
+    .unwrap()
+});
+
+/// Seconds between now and the timestamp of the last processed transaction
+pub static LAST_TRANSACTION_TIMESTAMP_LAG_SECS: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "indexer_last_transaction_timestamp_lag_secs",
+        "Seconds between now and the timestamp of the last processed transaction"
+    )
+    .unwrap()
+});
+
+pub static BATCHES_PROCESSED_COUNT: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "indexer_batches_processed_total",
+        "Number of transaction batches stored"
+    )
+    .unwrap()
+});
+
+/// Labelled by outcome: delivered, retried or failed
+pub static WEBHOOK_DELIVERIES_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_webhook_deliveries_total",
+        "Number of webhook delivery attempts, by outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+/// Labelled by the fully-qualified event type
+pub static EVENTS_PROCESSED_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_events_processed_total",
+        "Number of events decoded, by event type",
+        &["event_type"]
+    )
+    .unwrap()
+});
+
+/// Labelled by table name
+pub static ROWS_WRITTEN_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_rows_written_total",
+        "Number of rows inserted or updated, by table",
+        &["table"]
+    )
+    .unwrap()
+});
+
+/// Labelled by storer, each observation is one chunk written in a DB transaction
+pub static STORER_QUERY_LATENCY_SECS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "indexer_storer_query_latency_secs",
+        "Time taken to write a chunk of rows, by storer",
+        &["storer"]
+    )
+    .unwrap()
+});
+
+/// Labelled by pool and connection state (in_use or idle)
+pub static DB_POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_db_pool_connections",
+        "Number of connections in the DB pool, by state",
+        &["pool", "state"]
+    )
+    .unwrap()
+});
+
+/// Size of the out of order batches map in LatestVersionProcessedTracker
+pub static SEEN_VERSIONS_GAP_COUNT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "indexer_seen_versions_gap_count",
+        "Number of batches processed out of order and waiting for a gap to be filled"
+    )
+    .unwrap()
+});
+
+pub fn observe_db_pool(pool_name: &str, pool: &ArcDbPool) {
+    let state = pool.state();
+    DB_POOL_CONNECTIONS
+        .with_label_values(&[pool_name, "in_use"])
+        .set((state.connections - state.idle_connections) as i64);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&[pool_name, "idle"])
+        .set(state.idle_connections as i64);
+}
@@ -9,7 +9,7 @@ use std::sync::Arc;
 
 use super::database_utils::{ArcDbPool, DbPoolConnection};
 
-fn establish_connection(database_url: &str) -> BoxFuture<ConnectionResult<AsyncPgConnection>> {
+pub fn establish_connection(database_url: &str) -> BoxFuture<ConnectionResult<AsyncPgConnection>> {
     use native_tls::{Certificate, TlsConnector};
     use postgres_native_tls::MakeTlsConnector;
 
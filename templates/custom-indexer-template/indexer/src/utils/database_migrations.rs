@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use diesel::{migration::MigrationSource, pg::Pg};
+use diesel_async::{async_connection_wrapper::AsyncConnectionWrapper, AsyncPgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use super::database_connection::establish_connection;
+use crate::config::indexer_processor_config::DbConfig;
+
+/// Migrations under `src/db_migrations/migrations`, compiled into the binary so a deployment
+/// doesn't need diesel_cli to set up the database.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db_migrations/migrations");
+
+type MigrationConnection = AsyncConnectionWrapper<AsyncPgConnection>;
+
+/// Diesel's migration harness is synchronous, so run it on a blocking thread over a wrapped
+/// async connection. This reuses the same TLS setup as the connection pool.
+async fn with_migration_connection<F, T>(db_config: &DbConfig, f: F) -> Result<T>
+where
+    F: FnOnce(&mut MigrationConnection) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let conn = establish_connection(&db_config.postgres_connection_string)
+        .await
+        .context("Failed to connect to the database to run migrations")?;
+    let mut conn = MigrationConnection::from(conn);
+    tokio::task::spawn_blocking(move || f(&mut conn))
+        .await
+        .context("Migration task panicked")?
+}
+
+/// Applies every migration that hasn't been applied yet.
+pub async fn run_pending_migrations(db_config: &DbConfig) -> Result<()> {
+    tracing::info!("Running pending migrations");
+    with_migration_connection(db_config, |conn| {
+        let applied = conn
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Failed to run pending migrations")?;
+        if applied.is_empty() {
+            tracing::info!("No pending migrations");
+        }
+        for version in applied {
+            tracing::info!(version = %version, "Applied migration");
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Reverts the last `steps` applied migrations, newest first.
+pub async fn revert_migrations(db_config: &DbConfig, steps: usize) -> Result<()> {
+    with_migration_connection(db_config, move |conn| {
+        let applied_count = conn
+            .applied_migrations()
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Failed to read applied migrations")?
+            .len();
+        if steps > applied_count {
+            tracing::warn!(
+                steps = steps,
+                applied_count = applied_count,
+                "Requested more steps than there are applied migrations, reverting all of them"
+            );
+        }
+        for _ in 0..steps.min(applied_count) {
+            let version = conn
+                .revert_last_migration(MIGRATIONS)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to revert migration")?;
+            tracing::info!(version = %version, "Reverted migration");
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the name of every embedded migration in version order, with whether it has been
+/// applied to the database.
+pub async fn migration_status(db_config: &DbConfig) -> Result<Vec<(String, bool)>> {
+    with_migration_connection(db_config, |conn| {
+        let applied = conn
+            .applied_migrations()
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Failed to read applied migrations")?;
+        let mut migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Failed to load embedded migrations")?;
+        migrations.sort_by(|a, b| a.name().version().cmp(&b.name().version()));
+        Ok(migrations
+            .iter()
+            .map(|m| {
+                let is_applied = applied.contains(&m.name().version().as_owned());
+                (m.name().to_string(), is_applied)
+            })
+            .collect())
+    })
+    .await
+}
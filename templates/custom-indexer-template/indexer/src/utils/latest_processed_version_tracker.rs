@@ -10,6 +10,10 @@ use diesel::{query_dsl::methods::FilterDsl, upsert::excluded, ExpressionMethods}
 use std::marker::PhantomData;
 
 use super::{
+    counters::{
+        observe_db_pool, LAST_PROCESSED_VERSION, LAST_TRANSACTION_TIMESTAMP_LAG_SECS,
+        SEEN_VERSIONS_GAP_COUNT,
+    },
     database_connection::{get_db_connection, new_db_pool},
     database_execution::execute_with_better_error,
     database_utils::ArcDbPool,
@@ -84,8 +88,10 @@ where
                 .metadata
                 .end_transaction_timestamp
                 .as_ref()
-                .map(|t| parse_timestamp(t, last_success_batch.metadata.end_version as i64))
-                .map(|t| t.naive_utc());
+                .map(|t| parse_timestamp(t, last_success_batch.metadata.end_version as i64));
+            let lag_secs =
+                end_timestamp.map(|t| (chrono::Utc::now() - t).num_milliseconds() as f64 / 1000.0);
+            let end_timestamp = end_timestamp.map(|t| t.naive_utc());
             let status = ProcessorStatus {
                 processor: self.tracker_name.clone(),
                 last_success_version: last_success_batch.metadata.end_version as i64,
@@ -113,6 +119,10 @@ where
                     message: format!("Failed to update processor status: {}", e),
                     query: Some(format!("{:?}", query)),
                 })?;
+            LAST_PROCESSED_VERSION.set(last_success_batch.metadata.end_version as i64);
+            if let Some(lag_secs) = lag_secs {
+                LAST_TRANSACTION_TIMESTAMP_LAG_SECS.set(lag_secs);
+            }
         }
         Ok(())
     }
@@ -177,7 +187,8 @@ where
     }
 
     async fn poll(&mut self) -> Result<Option<Vec<TransactionContext<T>>>, ProcessorError> {
-        // TODO: Add metrics for gap count
+        SEEN_VERSIONS_GAP_COUNT.set(self.seen_versions.len() as i64);
+        observe_db_pool("tracker", &self.pool);
         self.save_processor_status().await?;
         // Nothing should be returned
         Ok(None)
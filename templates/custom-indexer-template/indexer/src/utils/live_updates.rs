@@ -0,0 +1,51 @@
+//! Rows the storers write, fanned out to the stream endpoints of the HTTP server. The storers
+//! publish after their DB transaction commits, so a streamed row can always be read back. Like
+//! `PIPELINE_STATE`, this is kept process-wide rather than threaded through.
+
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast;
+
+use crate::db_models::{message::Message, user_stat::UserStat};
+
+/// How far a subscriber can fall behind before it misses updates.
+const CHANNEL_CAPACITY: usize = 1024;
+
+pub static LIVE_UPDATES: Lazy<LiveUpdates> = Lazy::new(LiveUpdates::default);
+
+#[derive(Clone, Debug)]
+pub enum LiveUpdate {
+    /// A message as written by a create or update event.
+    Message(Message),
+    /// A user's stats after a batch of their events.
+    UserStat(UserStat),
+}
+
+pub struct LiveUpdates {
+    sender: broadcast::Sender<LiveUpdate>,
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl LiveUpdates {
+    /// Sends the rows written by a DB transaction to every current subscriber.
+    pub fn publish(&self, messages: Vec<Message>, user_stats: Vec<UserStat>) {
+        // Sending only fails when nobody is subscribed
+        for update in messages
+            .into_iter()
+            .map(LiveUpdate::Message)
+            .chain(user_stats.into_iter().map(LiveUpdate::UserStat))
+        {
+            let _ = self.sender.send(update);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.sender.subscribe()
+    }
+}
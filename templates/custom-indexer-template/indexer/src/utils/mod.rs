@@ -1,6 +1,12 @@
 pub mod chain_id;
+pub mod counters;
 pub mod database_connection;
 pub mod database_execution;
+pub mod database_migrations;
 pub mod database_utils;
 pub mod latest_processed_version_tracker;
+pub mod live_updates;
+pub mod move_abi;
+pub mod pipeline_state;
+pub mod rewind;
 pub mod starting_version;
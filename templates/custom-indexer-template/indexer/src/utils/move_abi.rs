@@ -0,0 +1,499 @@
+//! Helpers over the module ABIs stored in `module_upgrade_history.module_abi`, which hold the
+//! `MoveModule` of the transaction protos serialized as JSON.
+
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::{
+        move_function::Visibility, move_type::Content, MoveAbility, MoveFunction, MoveModule,
+        MoveStruct, MoveStructTag, MoveType, MoveTypes,
+    },
+    utils::convert::standardize_address,
+};
+use serde_json::Value;
+
+/// Parses a stored module ABI.
+pub fn parse_module_abi(module_abi: &Value) -> Result<MoveModule> {
+    serde_json::from_value(module_abi.clone()).context("Invalid module ABI")
+}
+
+/// Checks that event or resource data has the fields of `struct_name` in the module, with
+/// values of the JSON type each field's Move type is rendered as.
+pub fn check_struct_data(
+    module: &MoveModule,
+    struct_name: &str,
+    data: &Value,
+) -> Result<(), String> {
+    let move_struct = find_struct(module, struct_name).ok_or_else(|| {
+        format!(
+            "struct {} is not in the ABI of module {}",
+            struct_name, module.name
+        )
+    })?;
+    check_fields(module, move_struct, data, struct_name)
+}
+
+fn find_struct<'a>(module: &'a MoveModule, struct_name: &str) -> Option<&'a MoveStruct> {
+    module
+        .structs
+        .iter()
+        .find(|move_struct| move_struct.name == struct_name)
+}
+
+fn check_fields(
+    module: &MoveModule,
+    move_struct: &MoveStruct,
+    value: &Value,
+    path: &str,
+) -> Result<(), String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| format!("{} is not an object", path))?;
+    for field in &move_struct.fields {
+        let field_path = format!("{}.{}", path, field.name);
+        let field_value = object
+            .get(&field.name)
+            .ok_or_else(|| format!("{} is missing", field_path))?;
+        if let Some(move_type) = &field.r#type {
+            check_value(module, move_type, field_value, &field_path)?;
+        }
+    }
+    match object
+        .keys()
+        .find(|key| !move_struct.fields.iter().any(|field| &field.name == *key))
+    {
+        Some(key) => Err(format!("{}.{} is not a field of the struct", path, key)),
+        None => Ok(()),
+    }
+}
+
+fn check_value(
+    module: &MoveModule,
+    move_type: &MoveType,
+    value: &Value,
+    path: &str,
+) -> Result<(), String> {
+    let valid = match (move_type.r#type(), &move_type.content) {
+        (MoveTypes::Bool, _) => value.is_boolean(),
+        (MoveTypes::U8 | MoveTypes::U16 | MoveTypes::U32, _) => value.is_u64(),
+        // Integers that don't fit in a JSON number are rendered as decimal strings
+        (MoveTypes::U64 | MoveTypes::U128 | MoveTypes::U256, _) => value
+            .as_str()
+            .is_some_and(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())),
+        (MoveTypes::Address | MoveTypes::Signer, _) => value.is_string(),
+        (MoveTypes::Vector, Some(Content::Vector(item_type))) => {
+            // Byte vectors are rendered as hex strings
+            if item_type.r#type() == MoveTypes::U8 {
+                value.is_string()
+            } else {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| format!("{} is not an array", path))?;
+                for (idx, item) in items.iter().enumerate() {
+                    check_value(module, item_type, item, &format!("{}[{}]", path, idx))?;
+                }
+                true
+            }
+        }
+        (MoveTypes::Struct, Some(Content::Struct(struct_tag))) => {
+            return check_struct_value(module, struct_tag, value, path);
+        }
+        // Generic type parameters are only known per instance, the other types can't be fields
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid {:?}", path, move_type.r#type()))
+    }
+}
+
+fn check_struct_value(
+    module: &MoveModule,
+    struct_tag: &MoveStructTag,
+    value: &Value,
+    path: &str,
+) -> Result<(), String> {
+    let address = standardize_address(&struct_tag.address);
+    let is_framework = address == standardize_address("0x1");
+    let valid = match (struct_tag.module.as_str(), struct_tag.name.as_str()) {
+        ("string", "String") if is_framework => value.is_string(),
+        ("option", "Option") if is_framework => {
+            let items = value
+                .get("vec")
+                .and_then(Value::as_array)
+                .filter(|items| items.len() <= 1)
+                .ok_or_else(|| format!("{} is not a valid Option", path))?;
+            if let (Some(item), Some(item_type)) =
+                (items.first(), struct_tag.generic_type_params.first())
+            {
+                check_value(module, item_type, item, &format!("{}.vec[0]", path))?;
+            }
+            true
+        }
+        ("object", "Object") if is_framework => value.get("inner").is_some_and(Value::is_string),
+        (module_name, struct_name)
+            if address == standardize_address(&module.address) && module_name == module.name =>
+        {
+            match find_struct(module, struct_name) {
+                Some(move_struct) => return check_fields(module, move_struct, value, path),
+                None => value.is_object(),
+            }
+        }
+        // Structs of other modules, their ABI isn't at hand
+        _ => value.is_object(),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not a valid {}::{}",
+            path, struct_tag.module, struct_tag.name
+        ))
+    }
+}
+
+/// Renders a type the way Move source writes it, e.g. `vector<0x1::string::String>`.
+pub fn type_to_string(move_type: &MoveType) -> String {
+    match (move_type.r#type(), &move_type.content) {
+        (MoveTypes::Vector, Some(Content::Vector(item_type))) => {
+            format!("vector<{}>", type_to_string(item_type))
+        }
+        (MoveTypes::Struct, Some(Content::Struct(struct_tag))) => {
+            let name = format!(
+                "{}::{}::{}",
+                struct_tag.address, struct_tag.module, struct_tag.name
+            );
+            if struct_tag.generic_type_params.is_empty() {
+                name
+            } else {
+                format!(
+                    "{}<{}>",
+                    name,
+                    types_to_string(&struct_tag.generic_type_params)
+                )
+            }
+        }
+        (MoveTypes::GenericTypeParam, Some(Content::GenericTypeParamIndex(idx))) => {
+            format!("T{}", idx)
+        }
+        (MoveTypes::Reference, Some(Content::Reference(reference))) => format!(
+            "{}{}",
+            if reference.mutable { "&mut " } else { "&" },
+            reference
+                .to
+                .as_deref()
+                .map_or_else(String::new, type_to_string)
+        ),
+        (MoveTypes::Unparsable, Some(Content::Unparsable(unparsable))) => unparsable.clone(),
+        (move_types, _) => format!("{:?}", move_types).to_lowercase(),
+    }
+}
+
+fn types_to_string(move_types: &[MoveType]) -> String {
+    move_types
+        .iter()
+        .map(type_to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A difference between two upgrades of a module's ABI.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbiChange {
+    pub item_kind: AbiItemKind,
+    pub item_name: String,
+    pub change_type: AbiChangeType,
+    pub detail: String,
+    // Whether the chain accepts the change under the compatible upgrade policy
+    pub compatible: bool,
+}
+
+/// Stored in `module_abi_changes.item_kind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum AbiItemKind {
+    Function,
+    Struct,
+}
+
+/// Stored in `module_abi_changes.change_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum AbiChangeType {
+    Added,
+    Removed,
+    Changed,
+}
+
+fn abilities_to_string(abilities: &[MoveAbility]) -> String {
+    abilities
+        .iter()
+        .map(|ability| format!("{:?}", ability).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn fields_to_string(move_struct: &MoveStruct) -> String {
+    move_struct
+        .fields
+        .iter()
+        .map(|field| {
+            format!(
+                "{}: {}",
+                field.name,
+                field
+                    .r#type
+                    .as_ref()
+                    .map_or_else(String::new, type_to_string)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn signature_to_string(function: &MoveFunction) -> String {
+    format!(
+        "{}{}fun {}({}): ({})",
+        match function.visibility() {
+            Visibility::Public => "public ",
+            Visibility::Friend => "public(friend) ",
+            _ => "",
+        },
+        if function.is_entry { "entry " } else { "" },
+        function.name,
+        types_to_string(&function.params),
+        types_to_string(&function.r#return)
+    )
+}
+
+/// Whether other modules or transactions can depend on the function, which makes changing or
+/// removing it incompatible. Friend functions can change, friends are upgraded along with
+/// the module.
+fn is_linkable(function: &MoveFunction) -> bool {
+    function.visibility() == Visibility::Public || function.is_entry
+}
+
+/// Lists what changed from one upgrade of a module to the next, classified by the upgrade
+/// compatibility rules: structs can't be removed, or change their fields, type parameters or
+/// lose abilities, and public and entry functions can't be removed or change their signature.
+/// Anything can be added.
+pub fn diff_module_abis(old: &MoveModule, new: &MoveModule) -> Vec<AbiChange> {
+    let mut changes = vec![];
+
+    for old_struct in &old.structs {
+        let change = |change_type, detail, compatible| AbiChange {
+            item_kind: AbiItemKind::Struct,
+            item_name: old_struct.name.clone(),
+            change_type,
+            detail,
+            compatible,
+        };
+        let Some(new_struct) = find_struct(new, &old_struct.name) else {
+            changes.push(change(AbiChangeType::Removed, String::new(), false));
+            continue;
+        };
+        if old_struct.fields != new_struct.fields {
+            changes.push(change(
+                AbiChangeType::Changed,
+                format!(
+                    "fields changed from {{ {} }} to {{ {} }}",
+                    fields_to_string(old_struct),
+                    fields_to_string(new_struct)
+                ),
+                false,
+            ));
+        }
+        if old_struct.generic_type_params != new_struct.generic_type_params {
+            changes.push(change(
+                AbiChangeType::Changed,
+                format!(
+                    "type parameters changed from {} to {}",
+                    old_struct.generic_type_params.len(),
+                    new_struct.generic_type_params.len()
+                ),
+                false,
+            ));
+        }
+        let old_abilities = old_struct.abilities().collect::<Vec<_>>();
+        let new_abilities = new_struct.abilities().collect::<Vec<_>>();
+        let removed_abilities = old_abilities
+            .iter()
+            .filter(|ability| !new_abilities.contains(ability))
+            .copied()
+            .collect::<Vec<_>>();
+        let added_abilities = new_abilities
+            .iter()
+            .filter(|ability| !old_abilities.contains(ability))
+            .copied()
+            .collect::<Vec<_>>();
+        if !removed_abilities.is_empty() {
+            changes.push(change(
+                AbiChangeType::Changed,
+                format!(
+                    "abilities removed: {}",
+                    abilities_to_string(&removed_abilities)
+                ),
+                false,
+            ));
+        }
+        if !added_abilities.is_empty() {
+            changes.push(change(
+                AbiChangeType::Changed,
+                format!("abilities added: {}", abilities_to_string(&added_abilities)),
+                true,
+            ));
+        }
+    }
+    for new_struct in &new.structs {
+        if find_struct(old, &new_struct.name).is_none() {
+            changes.push(AbiChange {
+                item_kind: AbiItemKind::Struct,
+                item_name: new_struct.name.clone(),
+                change_type: AbiChangeType::Added,
+                detail: format!("{{ {} }}", fields_to_string(new_struct)),
+                compatible: true,
+            });
+        }
+    }
+
+    for old_function in &old.exposed_functions {
+        let new_function = new
+            .exposed_functions
+            .iter()
+            .find(|function| function.name == old_function.name);
+        match new_function {
+            None => changes.push(AbiChange {
+                item_kind: AbiItemKind::Function,
+                item_name: old_function.name.clone(),
+                change_type: AbiChangeType::Removed,
+                detail: signature_to_string(old_function),
+                compatible: !is_linkable(old_function),
+            }),
+            Some(new_function) if new_function != old_function => {
+                // Making a function public or entry only widens what can call it
+                let signature_kept = old_function.params == new_function.params
+                    && old_function.r#return == new_function.r#return
+                    && old_function.generic_type_params == new_function.generic_type_params;
+                let visibility_kept = old_function.visibility() != Visibility::Public
+                    || new_function.visibility() == Visibility::Public;
+                let entry_kept = !old_function.is_entry || new_function.is_entry;
+                changes.push(AbiChange {
+                    item_kind: AbiItemKind::Function,
+                    item_name: old_function.name.clone(),
+                    change_type: AbiChangeType::Changed,
+                    detail: format!(
+                        "{} became {}",
+                        signature_to_string(old_function),
+                        signature_to_string(new_function)
+                    ),
+                    compatible: !is_linkable(old_function)
+                        || (signature_kept && visibility_kept && entry_kept),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for new_function in &new.exposed_functions {
+        if !old
+            .exposed_functions
+            .iter()
+            .any(|function| function.name == new_function.name)
+        {
+            changes.push(AbiChange {
+                item_kind: AbiItemKind::Function,
+                item_name: new_function.name.clone(),
+                change_type: AbiChangeType::Added,
+                detail: signature_to_string(new_function),
+                compatible: true,
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::MoveStructField;
+
+    fn u64_type() -> MoveType {
+        MoveType {
+            r#type: MoveTypes::U64 as i32,
+            content: None,
+        }
+    }
+
+    fn function(name: &str, visibility: Visibility, params: Vec<MoveType>) -> MoveFunction {
+        MoveFunction {
+            name: name.to_string(),
+            visibility: visibility as i32,
+            params,
+            ..Default::default()
+        }
+    }
+
+    fn message_struct(abilities: Vec<MoveAbility>, fields: &[&str]) -> MoveStruct {
+        MoveStruct {
+            name: "Message".to_string(),
+            abilities: abilities
+                .into_iter()
+                .map(|ability| ability as i32)
+                .collect(),
+            fields: fields
+                .iter()
+                .map(|name| MoveStructField {
+                    name: name.to_string(),
+                    r#type: Some(u64_type()),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_module_abis() {
+        let old = MoveModule {
+            name: "message_board".to_string(),
+            structs: vec![message_struct(vec![MoveAbility::Key], &["created_at"])],
+            exposed_functions: vec![
+                function("get", Visibility::Public, vec![]),
+                function("helper", Visibility::Friend, vec![]),
+            ],
+            ..Default::default()
+        };
+        let new = MoveModule {
+            name: "message_board".to_string(),
+            structs: vec![message_struct(
+                vec![MoveAbility::Key, MoveAbility::Drop],
+                &["created_at", "updated_at"],
+            )],
+            exposed_functions: vec![
+                function("get", Visibility::Public, vec![u64_type()]),
+                function("count", Visibility::Public, vec![]),
+            ],
+            ..Default::default()
+        };
+
+        let changes = diff_module_abis(&old, &new)
+            .into_iter()
+            .map(|change| {
+                (
+                    change.item_name,
+                    change.change_type.to_string(),
+                    change.compatible,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("Message".to_string(), "changed".to_string(), false),
+                ("Message".to_string(), "changed".to_string(), true),
+                ("get".to_string(), "changed".to_string(), false),
+                ("helper".to_string(), "removed".to_string(), true),
+                ("count".to_string(), "added".to_string(), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! State of the running pipeline that the health server needs to answer readiness
+//! probes and serve the read API. The processor is constructed by the SDK's server framework, so like the
+//! Prometheus registry this is kept process-wide rather than threaded through.
+
+use chrono::Utc;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+
+use crate::{
+    config::indexer_processor_config::ReadinessConfig, schema::processor_status,
+    utils::database_utils::ArcDbPool,
+};
+
+/// How long the readiness probe waits for a DB connection before reporting the pool as down.
+const DB_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub static PIPELINE_STATE: Lazy<PipelineState> = Lazy::new(PipelineState::default);
+
+#[derive(Clone)]
+struct RegisteredPipeline {
+    db_pool: ArcDbPool,
+    processor_name: String,
+    readiness_config: ReadinessConfig,
+}
+
+#[derive(Default)]
+pub struct PipelineState {
+    pipeline: RwLock<Option<RegisteredPipeline>>,
+    // Unix millis of the last batch received from the transaction stream, 0 if none yet
+    last_batch_received_at_ms: AtomicI64,
+}
+
+/// Result of a single readiness check.
+#[derive(Debug, Serialize)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Body returned by the readiness probe.
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl ReadinessCheck {
+    fn new(name: &'static str, result: Result<(), String>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            reason: result.err(),
+        }
+    }
+}
+
+impl PipelineState {
+    /// Called once the processor has its DB pool, before the stream starts.
+    pub fn register(
+        &self,
+        db_pool: ArcDbPool,
+        processor_name: String,
+        readiness_config: ReadinessConfig,
+    ) {
+        *self.pipeline.write().unwrap() = Some(RegisteredPipeline {
+            db_pool,
+            processor_name,
+            readiness_config,
+        });
+    }
+
+    /// Pool of the running processor, None until it has started.
+    pub fn db_pool(&self) -> Option<ArcDbPool> {
+        self.pipeline
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|pipeline| pipeline.db_pool.clone())
+    }
+
+    pub fn processor_name(&self) -> Option<String> {
+        self.pipeline
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|pipeline| pipeline.processor_name.clone())
+    }
+
+    /// Called for every batch received from the transaction stream.
+    pub fn record_batch_received(&self) {
+        self.last_batch_received_at_ms
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub async fn check_readiness(&self) -> ReadinessReport {
+        let pipeline = self.pipeline.read().unwrap().clone();
+        let checks = match pipeline {
+            Some(pipeline) => {
+                let db = self.check_db(&pipeline).await;
+                let processor_status = match &db {
+                    Ok(()) => self.check_processor_status(&pipeline).await,
+                    Err(_) => Err("database is unreachable".to_string()),
+                };
+                vec![
+                    ReadinessCheck::new("database", db),
+                    ReadinessCheck::new("processor_status", processor_status),
+                    ReadinessCheck::new("transaction_stream", self.check_stream(&pipeline)),
+                ]
+            }
+            None => vec![ReadinessCheck::new(
+                "pipeline",
+                Err("processor has not started yet".to_string()),
+            )],
+        };
+
+        let failures = checks
+            .iter()
+            .filter_map(|check| {
+                check
+                    .reason
+                    .as_ref()
+                    .map(|reason| format!("{}: {}", check.name, reason))
+            })
+            .collect::<Vec<_>>();
+        ReadinessReport {
+            ready: failures.is_empty(),
+            reason: (!failures.is_empty()).then(|| failures.join("; ")),
+            checks,
+        }
+    }
+
+    async fn check_db(&self, pipeline: &RegisteredPipeline) -> Result<(), String> {
+        // bb8 validates connections on checkout, so getting one is enough
+        match tokio::time::timeout(DB_CHECK_TIMEOUT, pipeline.db_pool.get()).await {
+            Ok(Ok(_conn)) => Ok(()),
+            Ok(Err(e)) => Err(format!("failed to get a connection: {}", e)),
+            Err(_) => Err(format!(
+                "timed out getting a connection after {:?}",
+                DB_CHECK_TIMEOUT
+            )),
+        }
+    }
+
+    async fn check_processor_status(&self, pipeline: &RegisteredPipeline) -> Result<(), String> {
+        let mut conn = pipeline
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| format!("failed to get a connection: {}", e))?;
+        // Compare against the DB clock, last_updated is written with NOW()
+        let row = processor_status::table
+            .filter(processor_status::processor.eq(&pipeline.processor_name))
+            .select((processor_status::last_updated, diesel::dsl::now))
+            .first::<(chrono::NaiveDateTime, chrono::NaiveDateTime)>(&mut conn)
+            .await
+            .optional()
+            .map_err(|e| format!("failed to query processor status: {}", e))?;
+        let (last_updated, db_now) =
+            row.ok_or_else(|| "no processor status recorded yet".to_string())?;
+
+        let age_secs = (db_now - last_updated).num_seconds();
+        let max_age_secs = pipeline.readiness_config.max_processor_status_age_secs;
+        if age_secs > max_age_secs as i64 {
+            return Err(format!(
+                "last updated {}s ago, threshold is {}s",
+                age_secs, max_age_secs
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_stream(&self, pipeline: &RegisteredPipeline) -> Result<(), String> {
+        let last_batch_ms = self.last_batch_received_at_ms.load(Ordering::Relaxed);
+        if last_batch_ms == 0 {
+            return Err("no batch received yet".to_string());
+        }
+
+        let idle_secs = (Utc::now().timestamp_millis() - last_batch_ms) / 1000;
+        let max_idle_secs = pipeline.readiness_config.max_stream_idle_secs;
+        if idle_secs > max_idle_secs as i64 {
+            return Err(format!(
+                "no batch received for {}s, threshold is {}s",
+                idle_secs, max_idle_secs
+            ));
+        }
+        Ok(())
+    }
+}
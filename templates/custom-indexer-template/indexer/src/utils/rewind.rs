@@ -0,0 +1,353 @@
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use diesel::{
+    dsl::{max, min},
+    BoolExpressionMethods, ExpressionMethods, QueryDsl,
+};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use super::database_connection::establish_connection;
+use crate::{
+    config::{indexer_processor_config::IndexerProcessorConfig, points_config::UserPoints},
+    db_models::{
+        event_ledger::EventLedgerEntry, message_revision::MessageRevision, outbox::OutboxStatus,
+    },
+    schema::{
+        event_ledger, events, failed_items, leaderboard_snapshots, message_revisions, messages,
+        module_abi_changes, module_upgrade_history, outbox, package_upgrade_history,
+        processor_status, user_season_points, user_stats,
+    },
+    steps::{
+        event_handler::EventHandler,
+        storers::{
+            leaderboard_storer::{execute_leaderboard_rebase_sql, execute_leaderboard_sql},
+            user_season_points_storer::execute_user_season_points_sql,
+        },
+    },
+};
+
+/// Number of rows each part of a rewind touched, in the order they were applied.
+#[derive(Debug, Default)]
+pub struct RewindSummary {
+    pub changes: Vec<(&'static str, usize)>,
+}
+
+impl RewindSummary {
+    fn record(&mut self, change: &'static str, rows: usize) {
+        self.changes.push((change, rows));
+    }
+}
+
+/// Rolls the indexed state back to what it was right after `to_version` was processed, so the
+/// processor resumes from `to_version + 1`. Everything runs in a single transaction. With
+/// `dry_run` the transaction is rolled back after counting the affected rows.
+pub async fn rewind_to_version(
+    config: &IndexerProcessorConfig,
+    to_version: i64,
+    dry_run: bool,
+) -> Result<RewindSummary> {
+    let processor_name = config.processor_config.name().to_string();
+    let mut conn = establish_connection(&config.db_config.postgres_connection_string)
+        .await
+        .context("Failed to connect to the database")?;
+
+    let mut summary = RewindSummary::default();
+    let result = conn
+        .transaction(async |conn| {
+            rewind_sql(conn, to_version, &processor_name, &mut summary).await?;
+            if dry_run {
+                // Undo everything, the summary still has what would have changed
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+            Ok(())
+        })
+        .await;
+    match result {
+        Err(diesel::result::Error::RollbackTransaction) if dry_run => Ok(summary),
+        result => result
+            .map(|()| summary)
+            .context("Failed to rewind, nothing was changed"),
+    }
+}
+
+async fn rewind_sql(
+    conn: &mut AsyncPgConnection,
+    to_version: i64,
+    processor_name: &str,
+    summary: &mut RewindSummary,
+) -> diesel::QueryResult<()> {
+    // Messages created after the target version are deleted. They're found through the
+    // revision of their create event, so messages indexed before revisions were recorded are
+    // never deleted
+    let created_messages = message_revisions::table
+        .inner_join(
+            event_ledger::table.on(event_ledger::txn_version
+                .eq(message_revisions::txn_version)
+                .and(event_ledger::event_index.eq(message_revisions::event_index))),
+        )
+        .filter(message_revisions::txn_version.gt(to_version))
+        .filter(event_ledger::event_type.eq(EventHandler::CreateMessage.to_string()))
+        .select(message_revisions::message_obj_addr)
+        .load::<String>(conn)
+        .await?;
+    let rows = diesel::delete(
+        messages::table.filter(messages::message_obj_addr.eq_any(&created_messages)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("messages deleted", rows);
+
+    // Messages last updated after the target version go back to their latest revision at or
+    // before it
+    let restored_revisions = message_revisions::table
+        .inner_join(
+            messages::table.on(messages::message_obj_addr.eq(message_revisions::message_obj_addr)),
+        )
+        .filter(messages::last_update_txn_version.gt(to_version))
+        .filter(message_revisions::txn_version.le(to_version))
+        .distinct_on(message_revisions::message_obj_addr)
+        .order((
+            message_revisions::message_obj_addr,
+            message_revisions::revision_number.desc(),
+        ))
+        .select(message_revisions::all_columns)
+        .load::<MessageRevision>(conn)
+        .await?;
+    for revision in &restored_revisions {
+        diesel::update(messages::table.find(&revision.message_obj_addr))
+            .set((
+                messages::content.eq(&revision.content),
+                messages::last_update_timestamp.eq(revision.revision_timestamp),
+                messages::last_update_event_idx.eq(revision.event_index),
+                messages::last_update_txn_version.eq(revision.txn_version),
+            ))
+            .execute(conn)
+            .await?;
+    }
+    summary.record("messages restored", restored_revisions.len());
+
+    // Messages updated after the target version without a revision at or before it were
+    // created before revisions were recorded. Their content is kept, replaying the updates
+    // leaves it unchanged since it's already the latest
+    let rows = messages::table
+        .filter(messages::last_update_txn_version.gt(to_version))
+        .count()
+        .get_result::<i64>(conn)
+        .await?;
+    if rows > 0 {
+        tracing::warn!(
+            "{} messages updated after version {} have no revision at or before it, their \
+            content is kept as is",
+            rows,
+            to_version
+        );
+    }
+    summary.record("messages kept without a revision", rows as usize);
+
+    let rows = diesel::delete(
+        message_revisions::table.filter(message_revisions::txn_version.gt(to_version)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("message_revisions deleted", rows);
+
+    // Take the events that are no longer indexed back out of user_stats
+    let removed_events =
+        diesel::delete(event_ledger::table.filter(event_ledger::txn_version.gt(to_version)))
+            .returning((
+                event_ledger::user_addr,
+                event_ledger::event_type,
+                event_ledger::season_points,
+            ))
+            .get_results::<(String, String, serde_json::Value)>(conn)
+            .await?;
+    summary.record("event_ledger deleted", removed_events.len());
+    let rows = rewind_user_stats(conn, removed_events).await?;
+    summary.record("user_stats recomputed", rows);
+
+    let rows = diesel::delete(
+        module_abi_changes::table.filter(module_abi_changes::tx_version.gt(to_version)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("module_abi_changes deleted", rows);
+
+    let rows = diesel::delete(
+        module_upgrade_history::table.filter(module_upgrade_history::tx_version.gt(to_version)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("module_upgrade_history deleted", rows);
+
+    let rows = diesel::delete(
+        package_upgrade_history::table.filter(package_upgrade_history::tx_version.gt(to_version)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("package_upgrade_history deleted", rows);
+
+    let rows = diesel::delete(events::table.filter(events::txn_version.gt(to_version)))
+        .execute(conn)
+        .await?;
+    summary.record("events deleted", rows);
+
+    let rows = diesel::delete(failed_items::table.filter(failed_items::txn_version.gt(to_version)))
+        .execute(conn)
+        .await?;
+    summary.record("failed_items deleted", rows);
+
+    // Notifications already delivered are kept, so re-indexing doesn't deliver them again
+    let rows = diesel::delete(
+        outbox::table
+            .filter(outbox::tx_version.gt(to_version))
+            .filter(outbox::status.ne(OutboxStatus::Delivered.to_string())),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("outbox undelivered deleted", rows);
+
+    let rows = diesel::delete(
+        leaderboard_snapshots::table.filter(leaderboard_snapshots::txn_version.gt(to_version)),
+    )
+    .execute(conn)
+    .await?;
+    summary.record("leaderboard_snapshots deleted", rows);
+    if rows > 0 {
+        execute_leaderboard_rebase_sql(conn).await?;
+    }
+
+    let rows = diesel::update(
+        processor_status::table
+            .filter(processor_status::processor.eq(processor_name))
+            .filter(processor_status::last_success_version.gt(to_version)),
+    )
+    .set((
+        processor_status::last_success_version.eq(to_version),
+        processor_status::last_transaction_timestamp.eq(None::<chrono::NaiveDateTime>),
+    ))
+    .execute(conn)
+    .await?;
+    summary.record("processor_status reset", rows);
+
+    Ok(())
+}
+
+/// Subtracts the removed events from the stats and season points of their users and recomputes
+/// the timestamps from the events left in the ledger. Users left without any message are
+/// deleted. Points are taken back as stored in the ledger when the events were indexed.
+async fn rewind_user_stats(
+    conn: &mut AsyncPgConnection,
+    removed_events: Vec<(String, String, serde_json::Value)>,
+) -> diesel::QueryResult<usize> {
+    let create_message = EventHandler::CreateMessage.to_string();
+
+    // Key is user address
+    // Value is (number of removed create events, number of removed update events)
+    let mut removed_counts: AHashMap<String, (i64, i64)> = AHashMap::new();
+    let mut removed_points_events = vec![];
+    for (user_addr, event_type, season_points) in removed_events {
+        let (created, updated) = removed_counts.entry(user_addr.clone()).or_default();
+        match event_type.parse::<EventHandler>() {
+            Ok(handler) => {
+                match handler {
+                    EventHandler::CreateMessage => *created += 1,
+                    EventHandler::UpdateMessage => *updated += 1,
+                }
+                removed_points_events.push((
+                    user_addr,
+                    EventLedgerEntry::parse_season_points(season_points)?,
+                ));
+            }
+            Err(_) => tracing::warn!(
+                event_type = %event_type,
+                "Unknown event type in the ledger, it's not taken out of user_stats"
+            ),
+        }
+    }
+    if removed_counts.is_empty() {
+        return Ok(0);
+    }
+    let removed_points = UserPoints::from_events(removed_points_events);
+    let user_addrs: Vec<String> = removed_counts.keys().cloned().collect();
+
+    let latest_timestamps: AHashMap<String, Option<i64>> = event_ledger::table
+        .filter(event_ledger::user_addr.eq_any(&user_addrs))
+        .group_by(event_ledger::user_addr)
+        .select((event_ledger::user_addr, max(event_ledger::event_timestamp)))
+        .load::<(String, Option<i64>)>(conn)
+        .await?
+        .into_iter()
+        .collect();
+    let earliest_creation_timestamps: AHashMap<String, Option<i64>> = event_ledger::table
+        .filter(event_ledger::user_addr.eq_any(&user_addrs))
+        .filter(event_ledger::event_type.eq(&create_message))
+        .group_by(event_ledger::user_addr)
+        .select((event_ledger::user_addr, min(event_ledger::event_timestamp)))
+        .load::<(String, Option<i64>)>(conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    for (user_addr, (created, updated)) in &removed_counts {
+        let (total_points, s1_points) = removed_points
+            .totals
+            .get(user_addr)
+            .copied()
+            .unwrap_or_default();
+        // Stats accrued before the ledger existed have no ledger rows, keep their timestamps
+        let latest_timestamp = latest_timestamps.get(user_addr).copied().flatten();
+        let earliest_creation_timestamp = earliest_creation_timestamps
+            .get(user_addr)
+            .copied()
+            .flatten();
+        diesel::update(user_stats::table.find(user_addr))
+            .set((
+                user_stats::created_messages.eq(user_stats::created_messages - created),
+                user_stats::updated_messages.eq(user_stats::updated_messages - updated),
+                user_stats::s1_points.eq(user_stats::s1_points - s1_points),
+                user_stats::total_points.eq(user_stats::total_points - total_points),
+            ))
+            .execute(conn)
+            .await?;
+        if let Some(last_update_timestamp) = latest_timestamp {
+            diesel::update(user_stats::table.find(user_addr))
+                .set(user_stats::last_update_timestamp.eq(last_update_timestamp))
+                .execute(conn)
+                .await?;
+        }
+        if let Some(creation_timestamp) = earliest_creation_timestamp {
+            diesel::update(user_stats::table.find(user_addr))
+                .set(user_stats::creation_timestamp.eq(creation_timestamp))
+                .execute(conn)
+                .await?;
+        }
+    }
+
+    execute_user_season_points_sql(
+        conn,
+        removed_points
+            .seasons
+            .into_iter()
+            .map(|(key, points)| (key, -points))
+            .collect(),
+    )
+    .await?;
+
+    diesel::delete(
+        user_stats::table
+            .filter(user_stats::user_addr.eq_any(&user_addrs))
+            .filter(user_stats::created_messages.le(0))
+            .filter(user_stats::updated_messages.le(0)),
+    )
+    .execute(conn)
+    .await?;
+    diesel::delete(
+        user_season_points::table
+            .filter(user_season_points::user_addr.eq_any(&user_addrs))
+            .filter(user_season_points::points.eq(0)),
+    )
+    .execute(conn)
+    .await?;
+    execute_leaderboard_sql(conn, user_addrs).await?;
+    Ok(removed_counts.len())
+}
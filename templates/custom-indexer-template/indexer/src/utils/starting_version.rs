@@ -11,8 +11,7 @@ pub async fn get_starting_version(
     conn_pool: ArcDbPool,
 ) -> Result<u64> {
     let starting_version_from_config = indexer_processor_config
-        .transaction_stream_config
-        .starting_version
+        .configured_starting_version()
         .unwrap_or(0);
 
     let latest_processed_version_from_db =
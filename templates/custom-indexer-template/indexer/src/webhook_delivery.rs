@@ -0,0 +1,339 @@
+//! Delivers the webhook notifications the storers queue in the `outbox` table, configured by
+//! the `webhook_config` section of the config. Each notification is POSTed as JSON to its
+//! endpoint, signed with the endpoint's secret: the `X-Webhook-Signature` header is
+//! `sha256=` followed by the hex HMAC-SHA256 of `{timestamp}.{body}`, with the timestamp in
+//! `X-Webhook-Timestamp`. A 2xx response marks the notification delivered, anything else is
+//! retried with exponential backoff until `max_attempts` is reached, then it's marked failed.
+//! Delivery is at least once, receivers can drop duplicates by the `key` in the payload.
+
+use anyhow::{Context, Result};
+use hmac::{digest::KeyInit, Hmac, Mac};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, header, Request};
+use hyper_util::rt::TokioIo;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use url::{Position, Url};
+
+use crate::{
+    config::webhook_config::WebhookConfig,
+    db_models::outbox::OutboxNotification,
+    utils::{
+        counters::WEBHOOK_DELIVERIES_COUNT, database_connection::get_db_connection,
+        database_utils::ArcDbPool,
+    },
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+// Outbox row id, the same across the attempts of a notification
+pub const ID_HEADER: &str = "X-Webhook-Id";
+
+// Longest part of a failed response's body kept in outbox.last_error
+const MAX_ERROR_BODY_LEN: usize = 200;
+
+// Time past the request timeout a claimed notification is left to its instance, covering the
+// writes that record the outcome
+const CLAIM_LEASE_MARGIN_SECS: u64 = 60;
+
+/// Hex HMAC-SHA256 of `{timestamp}.{body}`, which receivers compute to check a payload.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// POSTs a signed JSON body, failing unless the response status is 2xx.
+pub async fn post_signed(
+    url: &Url,
+    secret: &str,
+    id: i64,
+    body: String,
+    timeout: Duration,
+) -> Result<()> {
+    let timestamp = chrono::Utc::now().timestamp();
+    let request = Request::post(&url[Position::BeforePath..])
+        .header(
+            header::HOST,
+            &url[Position::BeforeHost..Position::AfterPort],
+        )
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(ID_HEADER, id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", sign(secret, timestamp, &body)),
+        )
+        .body(Full::new(Bytes::from(body)))
+        .context("Failed to build the request")?;
+    tokio::time::timeout(timeout, send(url, request))
+        .await
+        .with_context(|| format!("No response within {:?}", timeout))?
+}
+
+async fn send(url: &Url, request: Request<Full<Bytes>>) -> Result<()> {
+    let host = url.host_str().context("Webhook URL has no host")?;
+    let port = url
+        .port_or_known_default()
+        .context("Webhook URL has no port")?;
+    let stream = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+    match url.scheme() {
+        "http" => send_over(stream, request).await,
+        "https" => {
+            let connector = tokio_native_tls::TlsConnector::from(
+                native_tls::TlsConnector::new().context("Failed to create the TLS connector")?,
+            );
+            let stream = connector
+                .connect(host, stream)
+                .await
+                .with_context(|| format!("TLS handshake with {} failed", host))?;
+            send_over(stream, request).await
+        }
+        scheme => anyhow::bail!("Unsupported webhook URL scheme {}", scheme),
+    }
+}
+
+async fn send_over<S>(stream: S, request: Request<Full<Bytes>>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .context("HTTP handshake failed")?;
+    // The connection is driven in the background until the response is received
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            tracing::debug!("Webhook connection closed with an error: {:?}", e);
+        }
+    });
+    let response = sender
+        .send_request(request)
+        .await
+        .context("Failed to send the request")?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map(|body| String::from_utf8_lossy(&body.to_bytes()).into_owned())
+        .unwrap_or_default();
+    anyhow::bail!(
+        "Endpoint responded {}: {}",
+        status,
+        body.chars().take(MAX_ERROR_BODY_LEN).collect::<String>()
+    )
+}
+
+/// Polls the outbox and delivers the notifications that are due.
+pub struct WebhookDeliveryWorker {
+    pool: ArcDbPool,
+    config: WebhookConfig,
+}
+
+impl WebhookDeliveryWorker {
+    pub fn new(pool: ArcDbPool, config: WebhookConfig) -> Self {
+        Self { pool, config }
+    }
+
+    /// How long the notifications claimed by deliver_due are hidden from other instances. The
+    /// requests run concurrently, so they all end within the request timeout.
+    fn claim_lease(&self) -> chrono::Duration {
+        chrono::Duration::seconds(
+            (self.config.request_timeout_secs + CLAIM_LEASE_MARGIN_SECS) as i64,
+        )
+    }
+
+    /// Delivers notifications until the process exits. Errors are logged and the outbox is
+    /// polled again at the next interval.
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.deliver_due().await {
+                tracing::warn!("Failed to deliver webhook notifications: {:#}", e);
+            }
+        }
+    }
+
+    /// Makes one attempt at each notification that is due, up to `batch_size` of them
+    /// concurrently, and records the outcome. Returns the number of notifications attempted.
+    pub async fn deliver_due(&self) -> Result<usize> {
+        let notifications = {
+            let conn = &mut get_db_connection(&self.pool).await?;
+            OutboxNotification::get_due(conn, self.config.batch_size, self.claim_lease())
+                .await
+                .context("Failed to load the due notifications")?
+        };
+        let results = futures_util::future::join_all(
+            notifications
+                .iter()
+                .map(|notification| self.deliver(notification)),
+        )
+        .await;
+
+        // Not held during the requests, which can take up to request_timeout_secs
+        let conn = &mut get_db_connection(&self.pool).await?;
+        for (notification, result) in notifications.iter().zip(results) {
+            match result {
+                Ok(()) => {
+                    notification.mark_delivered(conn).await?;
+                    WEBHOOK_DELIVERIES_COUNT
+                        .with_label_values(&["delivered"])
+                        .inc();
+                }
+                Err(error) => {
+                    let attempts = notification.attempts + 1;
+                    let next_attempt_at = (attempts < self.config.max_attempts).then(|| {
+                        chrono::Utc::now().naive_utc()
+                            + chrono::Duration::from_std(self.config.backoff(attempts))
+                                .expect("max_backoff_secs is out of range")
+                    });
+                    tracing::warn!(
+                        id = notification.id,
+                        endpoint = %notification.endpoint,
+                        attempts,
+                        retry_at = ?next_attempt_at,
+                        "Failed to deliver webhook notification: {:#}",
+                        error
+                    );
+                    notification
+                        .mark_attempt_failed(conn, &format!("{:#}", error), next_attempt_at)
+                        .await?;
+                    WEBHOOK_DELIVERIES_COUNT
+                        .with_label_values(&[if next_attempt_at.is_some() {
+                            "retried"
+                        } else {
+                            "failed"
+                        }])
+                        .inc();
+                }
+            }
+        }
+        Ok(notifications.len())
+    }
+
+    async fn deliver(&self, notification: &OutboxNotification) -> Result<()> {
+        let endpoint = self
+            .config
+            .endpoint(&notification.endpoint)
+            .with_context(|| format!("Endpoint {} isn't configured", notification.endpoint))?;
+        post_signed(
+            &endpoint.url,
+            &endpoint.secret,
+            notification.id,
+            notification.payload.to_string(),
+            Duration::from_secs(self.config.request_timeout_secs),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Local stand-in for a webhook receiver: answers each request with the next status and
+    /// returns the requests it received, head and body.
+    async fn stand_in(statuses: Vec<u16>) -> (Url, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/hooks", listener.local_addr().unwrap())).unwrap();
+        let handle = tokio::spawn(async move {
+            let mut requests = vec![];
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+                // Read until the whole body announced by Content-Length has arrived
+                loop {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let content_length = head
+                            .lines()
+                            .find_map(|line| {
+                                line.to_lowercase()
+                                    .strip_prefix("content-length: ")
+                                    .map(|len| len.parse::<usize>().unwrap())
+                            })
+                            .unwrap_or_default();
+                        if body.len() >= content_length {
+                            break;
+                        }
+                    }
+                    if read == 0 {
+                        break;
+                    }
+                }
+                socket
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {} Stand-in\r\ncontent-length: 4\r\nconnection: close\r\n\r\nnope",
+                            status
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> &'a str {
+        request
+            .lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once(": ")?;
+                key.eq_ignore_ascii_case(name).then_some(value)
+            })
+            .unwrap_or_else(|| panic!("Missing {} header", name))
+    }
+
+    #[tokio::test]
+    async fn test_post_signed() {
+        let (url, stand_in) = stand_in(vec![200, 503]).await;
+        let body = r#"{"kind":"message_created"}"#.to_string();
+        let timeout = Duration::from_secs(5);
+        post_signed(&url, "secret", 7, body.clone(), timeout)
+            .await
+            .unwrap();
+        let error = post_signed(&url, "secret", 7, body.clone(), timeout)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("503"), "{:#}", error);
+
+        let requests = stand_in.await.unwrap();
+        let request = &requests[0];
+        assert!(request.starts_with("POST /hooks HTTP/1.1"), "{}", request);
+        assert!(request.ends_with(&body), "{}", request);
+        assert_eq!(header(request, ID_HEADER), "7");
+        let timestamp = header(request, TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(
+            header(request, SIGNATURE_HEADER),
+            format!("sha256={}", sign("secret", timestamp, &body))
+        );
+    }
+}